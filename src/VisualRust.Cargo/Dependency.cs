@@ -15,6 +15,10 @@ namespace VisualRust.Cargo
         public Utf8String Git;
         public Utf8String Path;
         public Utf8String Target;
+        public Utf8String Branch;
+        public Utf8String Tag;
+        public Utf8String Rev;
+        public DependencyKind Kind;
     }
 
     [StructLayout(LayoutKind.Sequential)]
@@ -40,6 +44,14 @@ namespace VisualRust.Cargo
         }
     }
 
+    public enum DependencyKind
+    {
+        Registry = 0,
+        Git = 1,
+        Path = 2,
+        Detached = 3
+    }
+
     public class Dependency
     {
         public string Name { get; private set; }
@@ -47,6 +59,10 @@ namespace VisualRust.Cargo
         public string Git { get; private set; }
         public string Path { get; private set; }
         public string Target { get; private set; }
+        public string Branch { get; private set; }
+        public string Tag { get; private set; }
+        public string Rev { get; private set; }
+        public DependencyKind Kind { get; private set; }
 
         internal Dependency(RawDependency r)
         {
@@ -55,6 +71,10 @@ namespace VisualRust.Cargo
             Git = r.Git.ToString();
             Path = r.Path.ToString();
             Target = r.Target.ToString();
+            Branch = r.Branch.ToString();
+            Tag = r.Tag.ToString();
+            Rev = r.Rev.ToString();
+            Kind = r.Kind;
         }
     }
 }
@@ -36,6 +36,9 @@ namespace VisualRust.Cargo
         [DllImport("vist_toml.dll", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StringArrayQueryResult get_string_array(IntPtr manifest, RawSlice slice);
 
+        [DllImport("vist_toml.dll", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern int manifest_kind_at(IntPtr manifest, RawSlice slice);
+
         [DllImport("vist_toml.dll", CallingConvention = CallingConvention.Cdecl)]
         internal static extern DependenciesQueryResult get_dependencies(IntPtr manifest);
 
@@ -53,5 +56,8 @@ namespace VisualRust.Cargo
 
         [DllImport("vist_toml.dll", CallingConvention = CallingConvention.Cdecl)]
         internal static extern Utf8String manifest_to_string(IntPtr manifest);
+
+        [DllImport("vist_toml.dll", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern Utf8String manifest_last_error();
     }
 }
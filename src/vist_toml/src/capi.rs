@@ -1,8 +1,8 @@
+use std::cell::RefCell;
 use std::panic as std_panic;
 use std::ptr;
 use std::slice;
 
-use toml_document::{Document, ParserError};
 use winapi::INT32;
 
 use super::*;
@@ -10,6 +10,26 @@ use panic::*;
 
 type Boolean = u8;
 
+// Human-readable description of why the last call on this thread failed.
+// Every entry point except the free_* functions resets it, so the error
+// survives releasing the failed call's result.
+thread_local!(static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) });
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| e.borrow_mut().clear());
+}
+
+fn set_last_error<S: Into<String>>(msg: S) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg.into());
+}
+
+fn path_errors_message(errors: &[PathError]) -> String {
+    errors.iter()
+          .map(|e| e.to_string())
+          .collect::<Vec<_>>()
+          .join("\n")
+}
+
 #[repr(C)]
 pub struct ParseResult {
     pub manifest: *mut Manifest,
@@ -17,17 +37,18 @@ pub struct ParseResult {
 }
 
 impl ParseResult {
-    fn success(doc: Document) -> ParseResult {
+    fn success(manifest: Manifest) -> ParseResult {
         ParseResult {
-            manifest: Box::into_raw(Box::new(Manifest::new(doc))),
+            manifest: Box::into_raw(Box::new(manifest)),
             error: OwnedSlice::<u8>::empty()
         }
     }
 
-    fn error(err: ParserError) -> ParseResult {
+    fn error(err: ParseError) -> ParseResult {
+        set_last_error(err.message());
         ParseResult {
             manifest: ptr::null_mut(),
-            error: OwnedSlice::from_string(err.desc)
+            error: OwnedSlice::from_string(err.message())
         }
     }
 }
@@ -40,16 +61,19 @@ pub struct QueryResult<T> {
 
 impl QueryResult<OwnedSlice<u8>> {
     fn from_string_result(r: Result<&str, QueryError>) -> QueryResult<OwnedSlice<u8>> {
+        if let Err(ref err) = r {
+            set_last_error(err.to_string());
+        }
         match r {
             Ok(string) => QueryResult {
                 result: OwnedSlice::from_string(string),
                 error: QueryErrorFFI::empty()
             },
-            Err(QueryError::Vacant{ depth }) => QueryResult {
+            Err(QueryError::Vacant{ depth, .. }) => QueryResult {
                 result: OwnedSlice::empty(),
                 error: QueryErrorFFI::vacant(depth)
             },
-            Err(QueryError::Conflict{ depth, kind }) => QueryResult {
+            Err(QueryError::Conflict{ depth, kind, .. }) => QueryResult {
                 result: OwnedSlice::empty(),
                 error: QueryErrorFFI::conflict(depth, kind)
             },
@@ -60,16 +84,19 @@ impl QueryResult<OwnedSlice<u8>> {
 impl QueryResult<OwnedSlice<OwnedSlice<u8>>> {
     fn from_string_array_result(r: Result<Vec<&str>, QueryError>)
                                 -> QueryResult<OwnedSlice<OwnedSlice<u8>>> {
+        if let Err(ref err) = r {
+            set_last_error(err.to_string());
+        }
         match r {
             Ok(vec) => QueryResult {
                 result: OwnedSlice::from_slice(&vec, |s| OwnedSlice::from_string(*s)),
                 error: QueryErrorFFI::empty()
             },
-            Err(QueryError::Vacant{ depth }) => QueryResult {
+            Err(QueryError::Vacant{ depth, .. }) => QueryResult {
                 result: OwnedSlice::empty(),
                 error: QueryErrorFFI::vacant(depth)
             },
-            Err(QueryError::Conflict{ depth, kind }) => QueryResult {
+            Err(QueryError::Conflict{ depth, kind, .. }) => QueryResult {
                 result: OwnedSlice::empty(),
                 error: QueryErrorFFI::conflict(depth, kind)
             },
@@ -132,6 +159,9 @@ pub struct MultiQueryResult<T> {
 impl MultiQueryResult<OwnedSlice<RawDependency>> {
     fn from_dependencies(r: Result<Vec<Dependency>, Vec<PathError>>)
                          -> MultiQueryResult<OwnedSlice<RawDependency>> {
+        if let Err(ref errors) = r {
+            set_last_error(path_errors_message(errors));
+        }
         match r {
             Ok(deps) => MultiQueryResult {
                 result: OwnedSlice::from_slice(&deps, RawDependency::from),
@@ -148,6 +178,9 @@ impl MultiQueryResult<OwnedSlice<RawDependency>> {
 impl MultiQueryResult<OwnedSlice<OwnedOutputTarget>> {
     fn from_output_targets(r: Result<Vec<OutputTarget>, Vec<PathError>>)
                          -> MultiQueryResult<OwnedSlice<OwnedOutputTarget>> {
+        if let Err(ref errors) = r {
+            set_last_error(path_errors_message(errors));
+        }
         match r {
             Ok(targets) => MultiQueryResult {
                 result: OwnedSlice::from_slice(&targets, OwnedOutputTarget::from),
@@ -245,6 +278,11 @@ impl<'a> BorrowedOutputTarget<'a> {
             doc: self.doc.to_bool(),
             plugin: self.plugin.to_bool(),
             harness: self.harness.to_bool(),
+            // Not passed over the FFI, set_output_target leaves the keys alone
+            edition: None,
+            required_features: Vec::new(),
+            crate_type: Vec::new(),
+            proc_macro: false,
         }
     }
 }
@@ -257,11 +295,11 @@ pub extern "C" fn global_init() {
 #[no_mangle]
 pub extern "C" fn load_from_utf16(data: *const u16, len: INT32) -> ParseResult {
     unwindable_call(|| {
+        clear_last_error();
         let raw_text = unsafe { slice::from_raw_parts(data, len as usize) };
         let utf8_text = String::from_utf16_lossy(raw_text);
-        let doc_result = Document::parse(&utf8_text);
-        match doc_result {
-            Ok(doc) => ParseResult::success(doc),
+        match Manifest::parse(&utf8_text) {
+            Ok(manifest) => ParseResult::success(manifest),
             Err(parse_err) => ParseResult::error(parse_err)
         }
     })
@@ -300,6 +338,7 @@ pub extern "C" fn get_string<'a>(manifest: *mut Manifest,
                                  path: BorrowedSlice<'a, BorrowedSlice<'a, u8>>)
                                  -> QueryResult<OwnedSlice<u8>> {
     unwindable_call(move || {
+        clear_last_error();
         let vec = path.as_str_vec();
         let value = unsafe { &*manifest }.get_string(&vec);
         QueryResult::from_string_result(value)
@@ -312,16 +351,41 @@ pub extern "C" fn get_string_array<'a>(manifest: *mut Manifest,
                                        path: BorrowedSlice<'a, BorrowedSlice<'a, u8>>)
                                        -> QueryResult<OwnedSlice<OwnedSlice<u8>>> {
     unwindable_call(move || {
+        clear_last_error();
         let vec = path.as_str_vec();
         let value = unsafe { &*manifest }.get_string_array(&vec);
         QueryResult::from_string_array_result(value)
     })
 }
 
+// 0 = vacant, 1 = string, 2 = integer, 3 = float, 4 = boolean,
+// 5 = datetime, 6 = array, 7 = table
+#[no_mangle]
+#[allow(no_mangle_generic_items)]
+pub extern "C" fn manifest_kind_at<'a>(manifest: *const Manifest,
+                                       path: BorrowedSlice<'a, BorrowedSlice<'a, u8>>)
+                                       -> INT32 {
+    unwindable_call(move || {
+        clear_last_error();
+        let vec = path.as_str_vec();
+        match unsafe { &*manifest }.kind_at(&vec) {
+            None => 0,
+            Some(EntryKind::String) => 1,
+            Some(EntryKind::Integer) => 2,
+            Some(EntryKind::Float) => 3,
+            Some(EntryKind::Boolean) => 4,
+            Some(EntryKind::Datetime) => 5,
+            Some(EntryKind::Array) => 6,
+            Some(EntryKind::Table) => 7
+        }
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn get_dependencies(manifest: *mut Manifest)
                                    -> MultiQueryResult<OwnedSlice<RawDependency>> {
     unwindable_call(move || {
+        clear_last_error();
         let dependencies = unsafe { &*manifest }.get_dependencies();
         MultiQueryResult::from_dependencies(dependencies)
     })
@@ -331,6 +395,7 @@ pub extern "C" fn get_dependencies(manifest: *mut Manifest)
 pub extern "C" fn get_output_targets(manifest: *mut Manifest)
                                      -> MultiQueryResult<OwnedSlice<OwnedOutputTarget>> {
     unwindable_call(move || {
+        clear_last_error();
         let targets = unsafe { &*manifest }.get_output_targets();
         MultiQueryResult::from_output_targets(targets)
     })
@@ -342,6 +407,7 @@ pub extern "C" fn add_output_target<'a>(manifest: *mut Manifest,
                                         raw_target: BorrowedOutputTarget<'a>)
                                         -> usize {
     unwindable_call(move || {
+        clear_last_error();
         let manifest = unsafe { &mut*manifest };
         let target = raw_target.to_target();
         manifest.add_output_target(target)
@@ -354,9 +420,16 @@ pub extern "C" fn set_output_target<'a>(manifest: *mut Manifest,
                                         raw_target: BorrowedOutputTarget<'a>)
                                         -> usize {
     unwindable_call(move || {
+        clear_last_error();
         let manifest = unsafe { &mut*manifest };
         let target = raw_target.to_target();
-        manifest.set_output_target(target).unwrap_or(0)
+        match manifest.set_output_target(target) {
+            Ok(handle) => handle.unwrap_or(0),
+            Err(err) => {
+                set_last_error(err.to_string());
+                0
+            }
+        }
     })
 }
 
@@ -366,15 +439,81 @@ pub extern "C" fn remove_output_target<'a>(manifest: *mut Manifest,
                                            handle: usize,
                                            kind: BorrowedSlice<'a, u8>) {
     unwindable_call(move || {
+        clear_last_error();
         let manifest = unsafe { &mut*manifest };
-        manifest.remove_output_target(handle, kind.as_str())
+        if let Err(err) = manifest.remove_output_target(handle, kind.as_str()) {
+            set_last_error(err.to_string());
+        }
     })
 }
 
 #[no_mangle]
 pub extern "C" fn manifest_to_string(manifest: *mut Manifest) -> OwnedSlice<u8> {
     unwindable_call(move || {
+        clear_last_error();
         let manifest = unsafe { &*manifest };
         OwnedSlice::from_string(manifest.to_string())
     })
-}
\ No newline at end of file
+}
+
+#[no_mangle]
+pub extern "C" fn manifest_last_error() -> OwnedSlice<u8> {
+    unwindable_call(|| {
+        LAST_ERROR.with(|e| OwnedSlice::from_string(&*e.borrow()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+    use std::str;
+
+    use toml_document::Document;
+
+    use super::*;
+
+    fn m(text: &str) -> Manifest {
+        Manifest::new(Document::parse(text).unwrap())
+    }
+
+    fn borrowed<'a, T>(items: &'a [T]) -> BorrowedSlice<'a, T> {
+        BorrowedSlice {
+            data: RawSlice {
+                arr: items.as_ptr() as *mut _,
+                len: items.len() as INT32
+            },
+            marker: PhantomData
+        }
+    }
+
+    fn owned_str(slice: &OwnedSlice<u8>) -> &str {
+        let bytes = unsafe { slice::from_raw_parts(slice.data.arr, slice.data.len as usize) };
+        str::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn kind_at_tags() {
+        let manifest = m("s = \"x\"\ni = 1\nf = 1.5\nb = true\nd = 1979-05-27T07:32:00Z\n\
+            a = [1]\nt = {}\n");
+        let cases = [("nope", 0), ("s", 1), ("i", 2), ("f", 3), ("b", 4), ("d", 5), ("a", 6),
+                     ("t", 7)];
+        for &(key, tag) in &cases {
+            let segments = [borrowed(key.as_bytes())];
+            assert_eq!(manifest_kind_at(&manifest, borrowed(&segments)), tag);
+        }
+        let segments = [borrowed("s".as_bytes()), borrowed("x".as_bytes())];
+        assert_eq!(manifest_kind_at(&manifest, borrowed(&segments)), 0);
+    }
+
+    #[test]
+    fn last_error_after_failed_call() {
+        let mut manifest = m("[package]\nname = 1\n");
+        let segments = [borrowed("package".as_bytes()), borrowed("name".as_bytes())];
+        let result = get_string(&mut manifest, borrowed(&segments));
+        drop(result);
+        assert_eq!(owned_str(&manifest_last_error()), "unexpected integer at `package.name`");
+        let segments = [borrowed("package".as_bytes())];
+        assert_eq!(manifest_kind_at(&manifest, borrowed(&segments)), 7);
+        assert_eq!(owned_str(&manifest_last_error()), "");
+    }
+}
@@ -2,6 +2,8 @@ extern crate toml_document;
 extern crate winapi;
 extern crate kernel32;
 
+use std::cmp;
+use std::error;
 use std::fmt::{Display, Error, Formatter};
 use std::iter;
 use std::mem;
@@ -10,9 +12,10 @@ use std::slice;
 use std::str;
 use std::marker::PhantomData;
 
-use toml_document::{ArrayEntry, ArrayValueMut, Container, ContainerKind, Document};
-use toml_document::{EntryRef, EntryRefMut, InlineArray, InternalNode, InlineTable, TableEntry};
-use toml_document::{TableValue, ValueRefMut};
+use toml_document::{ArrayEntry, ArrayValue, ArrayValueMut, BoolValue, Container, ContainerKind};
+use toml_document::{DirectChild, Document, EntryRef, EntryRefMut, InlineArray, InlineTable};
+use toml_document::{InternalNode, StringValue, TableEntry, TableKeyMarkup, TableValue};
+use toml_document::{FloatValue, IntegerValue, ParserError, TableValueMut, ValueRef, ValueRefMut};
 use winapi::INT32;
 
 mod panic;
@@ -32,15 +35,7 @@ macro_rules! set_output_target {
 }
 
 fn entry_kind(e: EntryRef) -> &'static str {
-    match e {
-        EntryRef::String(..) => "string",
-        EntryRef::Integer(..) => "integer",
-        EntryRef::Float(..) => "float",
-        EntryRef::Boolean(..) => "boolean",
-        EntryRef::Datetime(..) => "datetime",
-        EntryRef::Array(..) => "array",
-        EntryRef::Table(..) => "table",
-    }
+    EntryKind::of(e).name()
 }
 
 fn array_kind(e: ArrayEntry) -> Option<&'static str> {
@@ -59,8 +54,445 @@ fn array_kind(e: ArrayEntry) -> Option<&'static str> {
     }
 }
 
+// Absent entry reads as an empty array
+fn get_string_array_entry<'a>(entry: Option<EntryRef<'a>>,
+                              path: String)
+                              -> Result<Vec<&'a str>, PathError> {
+    fn string_value<'a>(entry: EntryRef<'a>) -> &'a str {
+        match entry {
+            EntryRef::String(value) => value.get(),
+            _ => unreachable!()
+        }
+    }
+    match entry {
+        Some(EntryRef::Array(array)) => {
+            match array_kind(array) {
+                None => Ok(Vec::new()),
+                Some("array of strings") => Ok(array.iter().map(string_value).collect()),
+                Some(kind) => {
+                    Err(PathError {
+                        path,
+                        expected: "array of strings",
+                        got: kind,
+                        severity: Severity::Error
+                    })
+                }
+            }
+        }
+        Some(entry) => {
+            Err(PathError {
+                path,
+                expected: "array",
+                got: entry_kind(entry),
+                severity: Severity::Error
+            })
+        }
+        None => Ok(Vec::new())
+    }
+}
+
+// Spells a `[target.<key>]` key the way Cargo would compare it: whitespace
+// outside string literals dropped and single-argument `all()`/`any()` unwrapped
+fn normalize_target_key(key: &str) -> String {
+    let mut result = String::with_capacity(key.len());
+    let mut in_string = false;
+    for c in key.chars() {
+        if c == '"' {
+            in_string = !in_string;
+        }
+        if in_string || !c.is_whitespace() {
+            result.push(c);
+        }
+    }
+    loop {
+        let inner = if result.starts_with("cfg(all(") || result.starts_with("cfg(any(") {
+            &result[8..]
+        } else {
+            break
+        };
+        if !inner.ends_with("))") {
+            break
+        }
+        let inner = &inner[..inner.len() - 2];
+        let mut depth = 0;
+        let mut in_string = false;
+        let single = inner.chars().all(|c| {
+            match c {
+                '"' => in_string = !in_string,
+                '(' if !in_string => depth += 1,
+                ')' if !in_string => depth -= 1,
+                ',' if !in_string && depth == 0 => return false,
+                _ => {}
+            }
+            depth >= 0
+        });
+        if !single || inner.is_empty() {
+            break
+        }
+        result = format!("cfg({})", inner);
+    }
+    result
+}
+
+// Basic string literal, escaped the way the TOML spec asks for
+fn toml_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04X}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+// Bare key when possible, quoted otherwise
+fn toml_key(key: &str) -> String {
+    let is_bare = !key.is_empty()
+                  && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if is_bare {
+        key.to_owned()
+    } else {
+        toml_string(key)
+    }
+}
+
+// Compact JSON, object keys sorted like serde_json's default map does.
+// Datetimes become strings, non-finite floats become null.
+fn to_json(entry: EntryRef, out: &mut String) {
+    fn json_string(value: &str, out: &mut String) {
+        out.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c)
+            }
+        }
+        out.push('"');
+    }
+    match entry {
+        EntryRef::String(value) => json_string(value.get(), out),
+        EntryRef::Integer(value) => out.push_str(&value.get().to_string()),
+        EntryRef::Float(value) => {
+            let value = value.get();
+            if value.is_finite() {
+                out.push_str(&format!("{:?}", value));
+            } else {
+                out.push_str("null");
+            }
+        }
+        EntryRef::Boolean(value) => out.push_str(if value.get() { "true" } else { "false" }),
+        EntryRef::Datetime(value) => json_string(value.get(), out),
+        EntryRef::Array(array) => {
+            out.push('[');
+            for (idx, entry) in array.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                to_json(entry, out);
+            }
+            out.push(']');
+        }
+        EntryRef::Table(table) => {
+            let mut entries = table.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|&(a, _)| a);
+            out.push('{');
+            for (idx, (key, entry)) in entries.into_iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                json_string(key, out);
+                out.push(':');
+                to_json(entry, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn trailing_trivia<'a>(value: ValueRef<'a>) -> &'a str {
+    match value {
+        ValueRef::String(value) => value.markup().get_trailing_trivia(),
+        ValueRef::Integer(value) => value.markup().get_trailing_trivia(),
+        ValueRef::Float(value) => value.markup().get_trailing_trivia(),
+        ValueRef::Boolean(value) => value.markup().get_trailing_trivia(),
+        ValueRef::Datetime(value) => value.markup().get_trailing_trivia(),
+        ValueRef::Array(array) => array.markup().get_trailing_trivia(),
+        ValueRef::Table(table) => table.markup().get_trailing_trivia()
+    }
+}
+
+// Text of every `#` comment in a piece of trivia, one per line
+fn comment_text(trivia: &str) -> Vec<&str> {
+    trivia.lines()
+          .filter_map(|line| line.find('#').map(|idx| line[idx + 1..].trim()))
+          .collect()
+}
+
+// Matches a single path segment against a pattern with `*` and `?` wildcards
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// Whether a `[target.<key>]` section applies when building for `triple`. Knows
+// the usual `target_*` keys plus `unix`/`windows`, anything else (`test`,
+// `feature = ".."`, ...) is false. Unparsable cfg() never matches.
+fn target_applies(key: &str, triple: &str) -> bool {
+    fn cfg_value(name: &str, triple: &str) -> Option<String> {
+        let parts = triple.split('-').collect::<Vec<_>>();
+        let arch = match parts[0] {
+            "i386" | "i586" | "i686" => "x86",
+            arch if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+            arch if arch.starts_with("riscv64") => "riscv64",
+            arch if arch.starts_with("riscv32") => "riscv32",
+            arch => arch
+        };
+        let os = ["windows", "linux", "android", "macos", "ios", "freebsd", "netbsd", "openbsd",
+                  "dragonfly", "solaris", "illumos", "emscripten"]
+            .iter()
+            .cloned()
+            .find(|os| parts[1..].contains(os))
+            .or_else(|| if parts[1..].contains(&"darwin") { Some("macos") } else { None })
+            .unwrap_or("none");
+        let family = match os {
+            "windows" => "windows",
+            "none" if arch.starts_with("wasm") => "wasm",
+            "none" => "",
+            _ => "unix"
+        };
+        let env = parts.last()
+                       .and_then(|last| {
+                           ["gnu", "musl", "msvc", "sgx", "uclibc"].iter().find(|env| {
+                               parts.len() > 2 && last.starts_with(*env)
+                           })
+                       })
+                       .cloned()
+                       .unwrap_or("");
+        let width = match arch {
+            "x86_64" | "aarch64" | "powerpc64" | "powerpc64le" | "mips64" | "mips64el" |
+            "riscv64" | "sparc64" | "s390x" | "wasm64" => "64",
+            _ => "32"
+        };
+        let endian = match arch {
+            "powerpc" | "powerpc64" | "mips" | "mips64" | "s390x" | "sparc" | "sparc64" => "big",
+            _ => "little"
+        };
+        let value = match name {
+            "target_arch" => arch,
+            "target_os" => os,
+            "target_family" => family,
+            "target_env" => env,
+            "target_vendor" if parts.len() > 2 => parts[1],
+            "target_pointer_width" => width,
+            "target_endian" => endian,
+            _ => return None
+        };
+        Some(value.to_owned())
+    }
+    // pred = ident [ "=" string ] | ("all" | "any" | "not") "(" pred { "," pred } ")"
+    fn eval(tokens: &[String], pos: &mut usize, triple: &str) -> Option<bool> {
+        let name = match tokens.get(*pos) {
+            Some(name) if !name.starts_with('"') && name != "(" && name != ")" && name != ","
+                          && name != "=" => name.clone(),
+            _ => return None
+        };
+        *pos += 1;
+        match tokens.get(*pos).map(|t| &**t) {
+            Some("(") => {
+                *pos += 1;
+                let mut results = Vec::new();
+                while tokens.get(*pos).map(|t| &**t) != Some(")") {
+                    match eval(tokens, pos, triple) {
+                        Some(result) => results.push(result),
+                        None => return None
+                    }
+                    match tokens.get(*pos).map(|t| &**t) {
+                        Some(",") => *pos += 1,
+                        Some(")") => {}
+                        _ => return None
+                    }
+                }
+                *pos += 1;
+                match &*name {
+                    "all" => Some(results.iter().all(|r| *r)),
+                    "any" => Some(results.iter().any(|r| *r)),
+                    "not" if results.len() == 1 => Some(!results[0]),
+                    _ => None
+                }
+            }
+            Some("=") => {
+                *pos += 1;
+                let value = match tokens.get(*pos) {
+                    Some(value) if value.starts_with('"') => value.trim_matches('"').to_owned(),
+                    _ => return None
+                };
+                *pos += 1;
+                Some(cfg_value(&name, triple).is_some_and(|actual| actual == value))
+            }
+            _ => {
+                Some(match &*name {
+                    "unix" | "windows" => cfg_value("target_family", triple).unwrap() == name,
+                    _ => false
+                })
+            }
+        }
+    }
+    let key = key.trim();
+    if !key.starts_with("cfg(") || !key.ends_with(')') {
+        return key == triple;
+    }
+    let mut tokens = Vec::new();
+    let mut chars = key[4..key.len() - 1].chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | ')' | ',' | '=' => tokens.push(c.to_string()),
+            '"' => {
+                let mut token = String::from("\"");
+                for c in chars.by_ref() {
+                    token.push(c);
+                    if c == '"' {
+                        break;
+                    }
+                }
+                tokens.push(token);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        token.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    let mut pos = 0;
+    match eval(&tokens, &mut pos, triple) {
+        Some(result) => result && pos == tokens.len(),
+        None => false
+    }
+}
+
+// Structure of an SPDX license expression, identifiers themselves aren't checked:
+//   expr = and-expr { "OR" and-expr }
+//   and-expr = with-expr { "AND" with-expr }
+//   with-expr = ( id [ "+" ] | "(" expr ")" ) [ "WITH" id ]
+fn spdx_is_valid(expr: &str) -> bool {
+    fn is_id(token: &str) -> bool {
+        let id = token.strip_suffix('+').unwrap_or(token);
+        !id.is_empty() && id != "AND" && id != "OR" && id != "WITH" &&
+        id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == ':')
+    }
+    fn parse_or(tokens: &[&str], pos: &mut usize) -> bool {
+        if !parse_and(tokens, pos) {
+            return false;
+        }
+        while tokens.get(*pos) == Some(&"OR") {
+            *pos += 1;
+            if !parse_and(tokens, pos) {
+                return false;
+            }
+        }
+        true
+    }
+    fn parse_and(tokens: &[&str], pos: &mut usize) -> bool {
+        if !parse_with(tokens, pos) {
+            return false;
+        }
+        while tokens.get(*pos) == Some(&"AND") {
+            *pos += 1;
+            if !parse_with(tokens, pos) {
+                return false;
+            }
+        }
+        true
+    }
+    fn parse_with(tokens: &[&str], pos: &mut usize) -> bool {
+        match tokens.get(*pos) {
+            Some(&"(") => {
+                *pos += 1;
+                if !parse_or(tokens, pos) || tokens.get(*pos) != Some(&")") {
+                    return false;
+                }
+            }
+            Some(token) if is_id(token) => {}
+            _ => return false
+        }
+        *pos += 1;
+        if tokens.get(*pos) == Some(&"WITH") {
+            *pos += 1;
+            match tokens.get(*pos) {
+                Some(token) if is_id(token) && !token.ends_with('+') => *pos += 1,
+                _ => return false
+            }
+        }
+        true
+    }
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (idx, c) in expr.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(start) = start.take() {
+                tokens.push(&expr[start..idx]);
+            }
+            if !c.is_whitespace() {
+                tokens.push(&expr[idx..idx + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(idx);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&expr[start..]);
+    }
+    let mut pos = 0;
+    parse_or(&tokens, &mut pos) && pos == tokens.len()
+}
+
 pub struct Manifest {
-    doc: Document
+    doc: Document,
+    // Documents a reparse swapped out. Output target handles are node
+    // addresses, so the old nodes stay allocated and no new node can take a
+    // handle's address.
+    retired: Vec<Document>,
+    // (handle, node it names now) for handles that outlived a reparse
+    moved: Vec<(usize, usize)>
 }
 
 impl Display for Manifest {
@@ -72,26 +504,461 @@ impl Display for Manifest {
 // Set functions:
 // * if no table exists, create new top-level one
 // * overwrites value, even if it has a value but wrong type 
+// * keep output target handles valid, also when they have to reparse (see
+//   restore_header_newlines)
 impl Manifest {
     pub fn new(doc: Document) -> Manifest {
-        Manifest { doc: doc }
+        Manifest {
+            doc,
+            retired: Vec::new(),
+            moved: Vec::new()
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Manifest, ParseError> {
+        match Document::parse(text) {
+            Ok(doc) => Ok(Manifest::new(doc)),
+            Err(err) => Err(ParseError::new(text, err))
+        }
+    }
+
+    // Same text as Display, spelled out for callers writing the file back
+    pub fn serialize(&self) -> String {
+        self.doc.to_string()
     }
 
     pub fn get_string<'a, 'b:'a>(&'a self, path: &'b [&'b str]) -> Result<&'a str, QueryError> {
         match Manifest::lookup(&self.doc, path) {
             Ok(EntryRef::String(value)) => Ok(value.get()),
-            Ok(entry) => Err(QueryError::Conflict { depth: path.len(), kind: entry_kind(entry) }),
+            Ok(entry) => Err(QueryError::conflict(path, entry_kind(entry))),
+            Err(err) => Err(err)
+        }
+    }
+
+    pub fn get_bool<'a, 'b:'a>(&'a self, path: &'b [&'b str]) -> Result<bool, QueryError> {
+        match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Boolean(value)) => Ok(value.get()),
+            Ok(entry) => Err(QueryError::conflict(path, entry_kind(entry))),
+            Err(err) => Err(err)
+        }
+    }
+
+    pub fn get_integer<'a, 'b:'a>(&'a self, path: &'b [&'b str]) -> Result<i64, QueryError> {
+        match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Integer(value)) => Ok(value.get()),
+            Ok(entry) => Err(QueryError::conflict(path, entry_kind(entry))),
+            Err(err) => Err(err)
+        }
+    }
+
+    // Datetime as written, eg. `1979-05-27T07:32:00Z`; toml_document doesn't parse it
+    pub fn get_datetime<'a, 'b:'a>(&'a self, path: &'b [&'b str]) -> Result<&'a str, QueryError> {
+        match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Datetime(value)) => Ok(value.get()),
+            Ok(entry) => Err(QueryError::conflict(path, entry_kind(entry))),
+            Err(err) => Err(err)
+        }
+    }
+
+    pub fn get_float<'a, 'b:'a>(&'a self, path: &'b [&'b str]) -> Result<f64, QueryError> {
+        match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Float(value)) => Ok(value.get()),
+            Ok(entry) => Err(QueryError::conflict(path, entry_kind(entry))),
+            Err(err) => Err(err)
+        }
+    }
+
+    // Scalar at `path` together with its token as written, eg. `'a'` or `1_000`.
+    // Datetimes have no LeafValue, they're None like arrays and tables.
+    pub fn value_with_source<'a, 'b:'a>(&'a self,
+                                       path: &'b [&'b str])
+                                       -> Option<(LeafValue, &'a str)> {
+        match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::String(value)) => {
+                Some((LeafValue::String(value.get().to_owned()), value.raw()))
+            }
+            Ok(EntryRef::Integer(value)) => Some((LeafValue::Integer(value.get()), value.raw())),
+            Ok(EntryRef::Float(value)) => Some((LeafValue::Float(value.get()), value.raw())),
+            // Booleans have only one spelling
+            Ok(EntryRef::Boolean(value)) => {
+                let raw = if value.get() { "true" } else { "false" };
+                Some((LeafValue::Boolean(value.get()), raw))
+            }
+            _ => None
+        }
+    }
+
+    // Float exactly as written in the document, eg. `6.24e23` or `1_000.0`
+    pub fn get_float_raw<'a, 'b:'a>(&'a self, path: &'b [&'b str]) -> Result<&'a str, QueryError> {
+        match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Float(value)) => Ok(value.raw()),
+            Ok(entry) => Err(QueryError::conflict(path, entry_kind(entry))),
+            Err(err) => Err(err)
+        }
+    }
+
+    // crates.io links to docs.rs when the package doesn't set `documentation`
+    pub fn effective_documentation_url(&self) -> Result<String, QueryError> {
+        match self.get_string(&["package", "documentation"]) {
+            Ok(url) => Ok(url.to_owned()),
+            Err(QueryError::Vacant { .. }) => {
+                self.get_string(&["package", "name"]).map(|name| format!("https://docs.rs/{}", name))
+            }
             Err(err) => Err(err)
         }
     }
 
-    // It's the caller responsibility to make sure we are not
-    // setting value on a conflicting path, eg. for
+    // Requirement dependents should put next to `path`. Keeps major.minor of
+    // the version; pre-releases only match if the requirement names them,
+    // so those are kept whole (build metadata dropped).
+    pub fn suggest_path_dep_version(&self) -> Result<Option<String>, QueryError> {
+        let version = match self.get_string(&["package", "version"]) {
+            Ok(version) => version.split('+').next().unwrap(),
+            Err(QueryError::Vacant { .. }) => return Ok(None),
+            Err(err) => return Err(err)
+        };
+        if version.contains('-') {
+            return Ok(Some(format!("^{}", version)));
+        }
+        let major_minor = version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".");
+        Ok(Some(format!("^{}", major_minor)))
+    }
+
+    // `1.2.3` becomes `1.2.4-label.1`, `1.2.4-label.1` becomes `1.2.4-label.2`.
+    // Build metadata is kept. Writes the new version back and returns it.
+    pub fn next_prerelease(&mut self, label: &str) -> Result<String, BumpError> {
+        let next = {
+            let version = match self.get_string(&["package", "version"]) {
+                Ok(version) => version,
+                Err(err) => return Err(BumpError::Query(err))
+            };
+            let (version, build) = match version.find('+') {
+                Some(idx) => (&version[..idx], &version[idx..]),
+                None => (version, "")
+            };
+            let (core, pre) = match version.find('-') {
+                Some(idx) => (&version[..idx], Some(&version[idx + 1..])),
+                None => (version, None)
+            };
+            let numbers = core.split('.').map(|n| n.parse::<u64>()).collect::<Vec<_>>();
+            if numbers.len() != 3 || numbers.iter().any(|n| n.is_err()) {
+                return Err(BumpError::Malformed);
+            }
+            match pre {
+                None => {
+                    let patch = numbers[2].as_ref().unwrap() + 1;
+                    let major_minor = core.rsplit_once('.').unwrap().0;
+                    format!("{}.{}-{}.1{}", major_minor, patch, label, build)
+                }
+                Some(pre) => {
+                    let n = if pre.len() > label.len() + 1 && pre.starts_with(label)
+                               && pre[label.len()..].starts_with('.') {
+                        pre[label.len() + 1..].parse::<u64>().ok()
+                    } else {
+                        None
+                    };
+                    match n {
+                        Some(n) => format!("{}-{}.{}{}", core, label, n + 1, build),
+                        None => return Err(BumpError::OtherPrerelease)
+                    }
+                }
+            }
+        };
+        if self.set_string(&["package", "version"], &next) {
+            Ok(next)
+        } else {
+            Err(BumpError::WriteFailed)
+        }
+    }
+
+    // Only writes the editions Cargo knows about, so the project properties
+    // dropdown can't leave behind a manifest cargo refuses to read
+    pub fn set_edition(&mut self, edition: &str) -> Result<(), EditionError> {
+        if !EDITIONS.contains(&edition) {
+            return Err(EditionError::Unknown { allowed: EDITIONS });
+        }
+        if self.set_string(&["package", "edition"], edition) {
+            Ok(())
+        } else {
+            Err(EditionError::Conflict)
+        }
+    }
+
+    // Kind of the entry at `path`, or None if there's nothing there
+    pub fn kind_at<'a>(&'a self, path: &'a [&'a str]) -> Option<EntryKind> {
+        Manifest::lookup(&self.doc, path).ok().map(EntryKind::of)
+    }
+
+    // Returns false on a conflicting path, eg. for
     //   [[a]]
     //   b = "c"
-    // `set_string(&["a", "b"], "c")` will simply panic
-    pub fn set_string<'a>(&'a mut self, _: &'a [&'a str], _: &'a str) -> bool {
-        unimplemented!()
+    // `set_string(&["a", "b"], "c")` does nothing, same if `path` names a table.
+    // An existing string is updated in place, so it keeps its comments.
+    pub fn set_string<'a>(&'a mut self, path: &'a [&'a str], value: &'a str) -> bool {
+        let (key, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return false
+        };
+        let mut table = match self.parent_table_mut(parent) {
+            Some(table) => table,
+            None => return false
+        };
+        if let Some(string) = table.get_string_mut(key) {
+            string.set(value.to_owned());
+            return true;
+        }
+        match table.take_slot(key) {
+            Some(idx) => { table.insert_string(idx, key, value); }
+            None => return false
+        }
+        self.restore_header_newlines();
+        true
+    }
+
+    // Same rules as set_string
+    pub fn set_bool<'a>(&'a mut self, path: &'a [&'a str], value: bool) -> bool {
+        let (key, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return false
+        };
+        let mut table = match self.parent_table_mut(parent) {
+            Some(table) => table,
+            None => return false
+        };
+        if let Some(boolean) = table.get_bool_mut(key) {
+            boolean.set(value);
+            return true;
+        }
+        match table.take_slot(key) {
+            Some(idx) => { table.insert_boolean(idx, key, value); }
+            None => return false
+        }
+        self.restore_header_newlines();
+        true
+    }
+
+    // Same rules as set_string
+    pub fn set_integer<'a>(&'a mut self, path: &'a [&'a str], value: i64) -> bool {
+        let (key, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return false
+        };
+        let mut table = match self.parent_table_mut(parent) {
+            Some(table) => table,
+            None => return false
+        };
+        if let Some(integer) = table.get_integer_mut(key) {
+            integer.set(value);
+            return true;
+        }
+        match table.take_slot(key) {
+            Some(idx) => { table.insert_integer(idx, key, value); }
+            None => return false
+        }
+        self.restore_header_newlines();
+        true
+    }
+
+    // Same rules as set_string. toml_document can't
+    // read `inf` or `nan` back, so non-finite values are refused. Writing a
+    // whole number like `2.0` always reparses.
+    pub fn set_float<'a>(&'a mut self, path: &'a [&'a str], value: f64) -> bool {
+        if !value.is_finite() {
+            return false;
+        }
+        let literal = value.to_string();
+        if literal.contains('.') || literal.contains('e') {
+            return self.write_float(path, value);
+        }
+        // toml_document writes 2.0 as `2`, which reads back as an integer, and
+        // can't set a value's text. Add the `.0` right after the value written
+        // and reparse.
+        if !self.write_float(path, value) {
+            return false;
+        }
+        let text = match self.entry_span(path) {
+            Some((_, end)) => {
+                let text = self.doc.to_string();
+                format!("{}.0{}", &text[..end], &text[end..])
+            }
+            None => return false
+        };
+        match Document::parse(&text) {
+            Ok(doc) => {
+                self.reparse_keeping_handles(doc);
+                true
+            }
+            Err(..) => false
+        }
+    }
+
+    fn write_float<'a>(&'a mut self, path: &'a [&'a str], value: f64) -> bool {
+        let (key, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return false
+        };
+        let mut table = match self.parent_table_mut(parent) {
+            Some(table) => table,
+            None => return false
+        };
+        if let Some(float) = table.get_float_mut(key) {
+            float.set(value);
+            return true;
+        }
+        match table.take_slot(key) {
+            Some(idx) => { table.insert_float(idx, key, value); }
+            None => return false
+        }
+        self.restore_header_newlines();
+        true
+    }
+
+    // Removes the entry at `path`. A table with its own `[a.b]` header goes
+    // together with all its sub-tables, an emptied table is left in place.
+    // False if there was nothing to remove.
+    pub fn remove<'a>(&'a mut self, path: &'a [&'a str]) -> bool {
+        let (key, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return false
+        };
+        let is_child = match Manifest::lookup(&self.doc, path) {
+            Ok(entry) => entry.is_child(),
+            Err(..) => return false
+        };
+        if !is_child {
+            Manifest::remove_containers(&mut self.doc, path.iter().cloned());
+            return true;
+        }
+        match self.parent_table_mut(parent) {
+            Some(mut table) => { table.take_slot(key); }
+            None => return false
+        }
+        self.restore_header_newlines();
+        true
+    }
+
+    // `[a.b]` tables without keys or sub-tables, in document order. An empty
+    // `[workspace]` still marks the workspace root, it's never listed.
+    pub fn empty_tables(&self) -> Vec<Vec<String>> {
+        self.empty_table_indices()
+            .into_iter()
+            .map(|idx| {
+                let keys = self.doc.get_container(idx).keys().markup();
+                keys.iter().map(|key| key.get().to_owned()).collect()
+            })
+            .collect()
+    }
+
+    // Removes what empty_tables lists. A comment right above a header belongs
+    // to the key before it, so it stays. Tables that only become empty by
+    // this aren't removed. If anything is removed the document is reparsed,
+    // which invalidates target handles.
+    pub fn prune_empty_tables(&mut self) -> usize {
+        let indices = self.empty_table_indices();
+        if indices.is_empty() {
+            return 0;
+        }
+        // A table's text starts with the line break ending the previous one and
+        // the break after an empty header is in the trivia of whatever follows,
+        // so toml_document's remove would leave the lines around it wrong. Cut
+        // from the start of the table's first line to the end of its header
+        // line instead, and reparse.
+        let mut text = self.doc.to_string();
+        let mut ranges = Vec::new();
+        let mut offset = self.doc.iter_children().map(|c| c.to_string().len()).sum::<usize>();
+        for (idx, cnt) in self.doc.iter_containers().enumerate() {
+            let len = cnt.to_string().len();
+            if indices.contains(&idx) {
+                let (mut start, mut end) = (offset, offset + len);
+                if start > 0 && !text[..start].ends_with('\n')
+                   && text[start..end].starts_with('\n') {
+                    start += 1;
+                }
+                if !text[start..end].ends_with('\n') && text[end..].starts_with('\n') {
+                    end += 1;
+                }
+                ranges.push((start, end));
+            }
+            offset += len;
+        }
+        for (start, end) in ranges.into_iter().rev() {
+            text.drain(start..end);
+        }
+        match Document::parse(&text) {
+            Ok(doc) => {
+                self.replace_doc(doc);
+                indices.len()
+            }
+            Err(..) => 0
+        }
+    }
+
+    // Same rules as set_string. An existing inline
+    // array is emptied and refilled, anything else at `path` is replaced.
+    pub fn set_string_array<'a>(&'a mut self, path: &'a [&'a str], values: &[&str]) -> bool {
+        fn fill(array: &mut InlineArray, values: &[&str]) {
+            for (idx, value) in values.iter().enumerate() {
+                array.insert_string(idx, *value);
+            }
+        }
+        let (key, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return false
+        };
+        let mut table = match self.parent_table_mut(parent) {
+            Some(table) => table,
+            None => return false
+        };
+        if let Some(array) = table.get_array_mut(key) {
+            while array.len() > 0 {
+                array.remove(0);
+            }
+            fill(array, values);
+            return true;
+        }
+        match table.take_slot(key) {
+            Some(idx) => { fill(table.insert_array(idx, key), values); }
+            None => return false
+        }
+        self.restore_header_newlines();
+        true
+    }
+
+    // `package.exclude` as a set, false if `pattern` is already in it
+    pub fn add_exclude(&mut self, pattern: &str) -> bool {
+        const PATH: &[&str] = &["package", "exclude"];
+        let len = match self.get_string_array(PATH) {
+            Ok(patterns) if patterns.contains(&pattern) => return false,
+            Ok(patterns) => patterns.len(),
+            Err(QueryError::Vacant { .. }) => return self.set_string_array(PATH, &[pattern]),
+            Err(QueryError::Conflict { .. }) => return false
+        };
+        match self.parent_table_mut(&PATH[..1]).as_mut().and_then(|t| t.get_array_mut(PATH[1])) {
+            Some(array) => {
+                array.insert_string(len, pattern);
+                true
+            }
+            None => false
+        }
+    }
+
+    // False if `pattern` isn't in `package.exclude`
+    pub fn remove_exclude(&mut self, pattern: &str) -> bool {
+        const PATH: &[&str] = &["package", "exclude"];
+        let idx = match self.get_string_array(PATH) {
+            Ok(patterns) => match patterns.iter().position(|p| *p == pattern) {
+                Some(idx) => idx,
+                None => return false
+            },
+            Err(..) => return false
+        };
+        match self.parent_table_mut(&PATH[..1]).as_mut().and_then(|t| t.get_array_mut(PATH[1])) {
+            Some(array) => {
+                array.remove(idx);
+                true
+            }
+            None => false
+        }
     }
 
     pub fn get_string_array<'a>(&'a self,
@@ -110,57 +977,353 @@ impl Manifest {
                 }
                 match array.get(0) {
                     EntryRef::String(_) => Ok(array.iter().map(string_value).collect()),
-                    entry => Err(QueryError::Conflict { depth: path.len(), kind: entry_kind(entry) })
+                    entry => Err(QueryError::conflict(path, entry_kind(entry)))
                 }
             }
-            Ok(entry) => Err(QueryError::Conflict { depth: path.len(), kind: entry_kind(entry) }),
+            Ok(entry) => Err(QueryError::conflict(path, entry_kind(entry))),
             Err(err) => Err(err)
         }
     }
 
-    pub fn get_dependencies(&self) -> Result<Vec<Dependency>, Vec<PathError>> {
-        fn get_inner<'a>(deps: &mut Vec<Dependency<'a>>,
-                         errors: &mut Vec<PathError>,
-                         target: Option<&'a str>,
-                         entry: EntryRef<'a>) {
-            match entry {
-                EntryRef::Table(table) => {
-                    for (name, entry) in table.iter() {
-                        match entry {
-                            EntryRef::String(version) => {
-                                deps.push(Dependency::simple(name, target, version.get()));
-                            }
-                            EntryRef::Table(table) => {
-                                deps.push(Dependency::complex(name, target, table));
-                            }
-                            entry => {
-                                let path = match target {
-                                    Some(target) => {
-                                        format!("target.{}.dependencies.{}", target, name)
-                                    }
-                                    None => format!("dependencies.{}", name)
-                                };
-                                let error = PathError {
+    // Renames the package. With `update_targets`, [lib] and [[bin]] entries whose
+    // explicit `name` was the old package name get renamed too.
+    // Returns false if there's no `package.name` string to rename.
+    pub fn rename_package(&mut self, new_name: &str, update_targets: bool) -> bool {
+        fn rename(entry: Option<EntryRefMut>, old_name: &str, new_name: &str) {
+            if let Some(EntryRefMut::String(value)) = entry {
+                if value.get() == old_name {
+                    value.set(new_name.to_owned());
+                }
+            }
+        }
+        let old_name = match self.get_string(&["package", "name"]) {
+            Ok(name) => name.to_owned(),
+            Err(..) => return false
+        };
+        rename(Manifest::lookup_mut(&mut self.doc, &["package", "name"]), &old_name, new_name);
+        if !update_targets {
+            return true;
+        }
+        rename(Manifest::lookup_mut(&mut self.doc, &["lib", "name"]), &old_name, new_name);
+        for idx in 0..self.doc.len_containers() {
+            let container = self.doc.get_container_mut(idx);
+            if Manifest::is_array_member(container, "bin") {
+                rename(container.get_mut("name"), &old_name, new_name);
+            }
+        }
+        if let Some(EntryRefMut::Array(array)) = self.doc.get_mut("bin") {
+            if let ArrayValueMut::Inline(inline_array) = array.to_value() {
+                for idx in 0..inline_array.len() {
+                    if let ValueRefMut::Table(table) = inline_array.get_mut(idx) {
+                        rename(table.to_entry_mut().get_mut("name"), &old_name, new_name);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // [package.metadata] as a JSON object, None if the package has none
+    pub fn metadata_json(&self) -> Result<Option<String>, QueryError> {
+        match Manifest::lookup(&self.doc, &["package", "metadata"]) {
+            Ok(entry @ EntryRef::Table(..)) => {
+                let mut json = String::new();
+                to_json(entry, &mut json);
+                Ok(Some(json))
+            }
+            Ok(entry) => Err(QueryError::conflict(&["package", "metadata"], entry_kind(entry))),
+            Err(QueryError::Vacant { .. }) => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+
+    // The cargo-release settings we show, everything else in the table is ignored.
+    // `pre-release-hook` may be written as a single command line or as an argv array.
+    pub fn get_release_metadata(&self) -> Result<ReleaseConfig, Vec<PathError>> {
+        fn get_string(table: TableEntry, key: &str, errors: &mut Vec<PathError>) -> Option<String> {
+            match table.get(key) {
+                Some(EntryRef::String(value)) => Some(value.get().to_owned()),
+                Some(entry) => {
+                    errors.push(PathError {
+                        path: format!("package.metadata.release.{}", key),
+                        expected: "string",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    });
+                    None
+                }
+                None => None
+            }
+        }
+        fn get_bool(table: TableEntry, key: &str, errors: &mut Vec<PathError>) -> Option<bool> {
+            match table.get(key) {
+                Some(EntryRef::Boolean(value)) => Some(value.get()),
+                Some(entry) => {
+                    errors.push(PathError {
+                        path: format!("package.metadata.release.{}", key),
+                        expected: "boolean",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    });
+                    None
+                }
+                None => None
+            }
+        }
+        fn get_array(table: TableEntry,
+                     key: &str,
+                     errors: &mut Vec<PathError>)
+                     -> Option<Vec<String>> {
+            let entry = match table.get(key) {
+                Some(EntryRef::String(value)) if key == "pre-release-hook" => {
+                    return Some(vec![value.get().to_owned()]);
+                }
+                Some(entry) => entry,
+                None => return None
+            };
+            let path = format!("package.metadata.release.{}", key);
+            match get_string_array_entry(Some(entry), path) {
+                Ok(values) => Some(values.iter().map(|v| v.to_string()).collect()),
+                Err(error) => {
+                    errors.push(error);
+                    None
+                }
+            }
+        }
+        let mut config = ReleaseConfig {
+            sign_commit: None,
+            sign_tag: None,
+            push: None,
+            publish: None,
+            tag_name: None,
+            pre_release_commit_message: None,
+            pre_release_hook: None,
+            allow_branch: None
+        };
+        let table = match Manifest::lookup(&self.doc, &["package", "metadata", "release"]) {
+            Ok(EntryRef::Table(table)) => table,
+            Ok(entry) => {
+                let error = PathError {
+                    path: "package.metadata.release".to_owned(),
+                    expected: "table",
+                    got: entry_kind(entry),
+                    severity: Severity::Error
+                };
+                return Err(vec![error]);
+            }
+            Err(..) => return Ok(config)
+        };
+        let mut errors = Vec::new();
+        config.sign_commit = get_bool(table, "sign-commit", &mut errors);
+        config.sign_tag = get_bool(table, "sign-tag", &mut errors);
+        config.push = get_bool(table, "push", &mut errors);
+        config.publish = get_bool(table, "publish", &mut errors);
+        config.tag_name = get_string(table, "tag-name", &mut errors);
+        config.pre_release_commit_message = get_string(table,
+                                                       "pre-release-commit-message",
+                                                       &mut errors);
+        config.pre_release_hook = get_array(table, "pre-release-hook", &mut errors);
+        config.allow_branch = get_array(table, "allow-branch", &mut errors);
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(config)
+        }
+    }
+
+    // Badges and their fields in document order, no `[badges]` is no badges
+    pub fn badges_display(&self) -> Result<Vec<BadgeDisplay<'_>>, Vec<PathError>> {
+        let table = match self.doc.get("badges") {
+            Some(EntryRef::Table(table)) => table,
+            Some(entry) => return Err(vec![PathError {
+                path: "badges".to_owned(),
+                expected: "table",
+                got: entry_kind(entry),
+                severity: Severity::Error
+            }]),
+            None => return Ok(Vec::new())
+        };
+        let mut badges = Vec::new();
+        let mut errors = Vec::new();
+        for provider in self.ordered_keys(&["badges"]) {
+            let badge = match table.get(provider).unwrap() {
+                EntryRef::Table(badge) => badge,
+                entry => {
+                    errors.push(PathError {
+                        path: format!("badges.{}", provider),
+                        expected: "table",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    });
+                    continue;
+                }
+            };
+            let mut fields = Vec::new();
+            for key in badge.iter_children().map(|child| child.key().get()) {
+                match badge.get(key).unwrap() {
+                    EntryRef::String(value) => fields.push((key, value.get())),
+                    entry => errors.push(PathError {
+                        path: format!("badges.{}.{}", provider, key),
+                        expected: "string",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    })
+                }
+            }
+            badges.push(BadgeDisplay { provider, fields });
+        }
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(badges)
+        }
+    }
+
+    // `keys` are relative to `package.metadata`. Arrays and tables aren't leaves,
+    // neither are datetimes since LeafValue has no variant for them: all three
+    // are a Conflict.
+    pub fn get_metadata_scalar(&self, keys: &[&str]) -> Result<Option<LeafValue>, QueryError> {
+        let mut path = vec!["package", "metadata"];
+        path.extend_from_slice(keys);
+        let leaf = match Manifest::lookup(&self.doc, &path) {
+            Ok(EntryRef::String(value)) => LeafValue::String(value.get().to_owned()),
+            Ok(EntryRef::Integer(value)) => LeafValue::Integer(value.get()),
+            Ok(EntryRef::Float(value)) => LeafValue::Float(value.get()),
+            Ok(EntryRef::Boolean(value)) => LeafValue::Boolean(value.get()),
+            Ok(entry) => return Err(QueryError::conflict(&path, entry_kind(entry))),
+            Err(QueryError::Vacant { .. }) => return Ok(None),
+            Err(err) => return Err(err)
+        };
+        Ok(Some(leaf))
+    }
+
+    // Creates `[package.metadata.x.y]` for `keys` [x, y, z] if needed. Fails if
+    // one of the tables on the way is inline or `z` is a table with its own header.
+    pub fn set_metadata_scalar(&mut self, keys: &[&str], value: LeafValue) -> bool {
+        let (leaf, tables) = match keys.split_last() {
+            Some(split) => split,
+            None => return false
+        };
+        let mut path = vec!["package", "metadata"];
+        path.extend_from_slice(tables);
+        let cnt = match self.table_container_mut(&path) {
+            Some(cnt) => cnt,
+            None => return false
+        };
+        if cnt.get(leaf).is_some_and(|entry| !entry.is_child()) {
+            return false;
+        }
+        // Values of the same type are updated in place, keeping their trivia
+        match (cnt.get_mut(leaf), &value) {
+            (Some(EntryRefMut::String(old)), LeafValue::String(new)) => {
+                old.set(new.clone());
+                return true;
+            }
+            (Some(EntryRefMut::Integer(old)), &LeafValue::Integer(new)) => {
+                old.set(new);
+                return true;
+            }
+            (Some(EntryRefMut::Float(old)), &LeafValue::Float(new)) => {
+                old.set(new);
+                return true;
+            }
+            (Some(EntryRefMut::Boolean(old)), &LeafValue::Boolean(new)) => {
+                old.set(new);
+                return true;
+            }
+            _ => {}
+        }
+        let index = match cnt.iter_children().position(|c| c.key().get() == *leaf) {
+            Some(index) => {
+                cnt.remove(index);
+                index
+            }
+            None => cnt.len_children()
+        };
+        match value {
+            LeafValue::String(value) => { cnt.insert_string(index, *leaf, value); }
+            LeafValue::Integer(value) => { cnt.insert_integer(index, *leaf, value); }
+            LeafValue::Float(value) => { cnt.insert_float(index, *leaf, value); }
+            LeafValue::Boolean(value) => { cnt.insert_boolean(index, *leaf, value); }
+        }
+        self.restore_header_newlines();
+        true
+    }
+
+    // Names of the tools that keep a table under [package.metadata]
+    pub fn metadata_tools(&self) -> Vec<&str> {
+        let path = &["package", "metadata"];
+        let metadata = match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Table(table)) => table,
+            _ => return Vec::new()
+        };
+        self.ordered_keys(path)
+            .into_iter()
+            .filter(|key| matches!(metadata.get(key), Some(EntryRef::Table(..))))
+            .collect()
+    }
+
+    pub fn get_dependencies(&self) -> Result<Vec<Dependency<'_>>, Vec<PathError>> {
+        self.dependencies_in(DependencySection::Normal)
+    }
+
+    pub fn get_dev_dependencies(&self) -> Result<Vec<Dependency<'_>>, Vec<PathError>> {
+        self.dependencies_in(DependencySection::Dev)
+    }
+
+    pub fn get_build_dependencies(&self) -> Result<Vec<Dependency<'_>>, Vec<PathError>> {
+        self.dependencies_in(DependencySection::Build)
+    }
+
+    // `[section]` and all the `[target.*.section]` tables
+    fn dependencies_in(&self,
+                       kind: DependencySection)
+                       -> Result<Vec<Dependency<'_>>, Vec<PathError>> {
+        fn get_inner<'a>(deps: &mut Vec<Dependency<'a>>,
+                         errors: &mut Vec<PathError>,
+                         kind: DependencySection,
+                         target: Option<&'a str>,
+                         entry: EntryRef<'a>) {
+            let section = kind.key();
+            match entry {
+                EntryRef::Table(table) => {
+                    for (name, entry) in table.iter() {
+                        let path = match target {
+                            Some(target) => format!("target.{}.{}.{}", target, section, name),
+                            None => format!("{}.{}", section, name)
+                        };
+                        let mut dep = match entry {
+                            EntryRef::String(version) => {
+                                Dependency::simple(name, target, version.get())
+                            }
+                            EntryRef::Table(table) => {
+                                Dependency::complex(name, target, table, &path, errors)
+                            }
+                            entry => {
+                                let error = PathError {
                                     path: path,
                                     expected: "string",
-                                    got: entry_kind(entry)
+                                    got: entry_kind(entry),
+                                    severity: Severity::Error
                                 };
                                 errors.push(error);
+                                continue;
                             }
-                        }
+                        };
+                        dep.section = kind;
+                        deps.push(dep);
                     }
                 }
                 entry => {
                     let path = match target {
-                        Some(target) => {
-                            format!("target.{}.dependencies", target)
-                        }
-                        None => "dependencies".to_owned()
+                        Some(target) => format!("target.{}.{}", target, section),
+                        None => section.to_owned()
                     };
                     let error = PathError {
                         path: path,
                         expected: "table",
-                        got: entry_kind(entry)
+                        got: entry_kind(entry),
+                        severity: Severity::Error
                     };
                     errors.push(error);
                 }
@@ -168,14 +1331,14 @@ impl Manifest {
         }
         let mut deps = Vec::new();
         let mut errors = Vec::new();
-        if let Some(entry) = self.doc.get("dependencies") {
-            get_inner(&mut deps, &mut errors, None, entry);
+        if let Some(entry) = self.doc.get(kind.key()) {
+            get_inner(&mut deps, &mut errors, kind, None, entry);
         }
         if let Some(EntryRef::Table(targets)) = self.doc.get("target") {
             for (target, target_entry) in targets.iter() {
                 if let EntryRef::Table(target_table) = target_entry {
-                    if let Some(entry) = target_table.get("dependencies") {
-                        get_inner(&mut deps, &mut errors, Some(target), entry);
+                    if let Some(entry) = target_table.get(kind.key()) {
+                        get_inner(&mut deps, &mut errors, kind, Some(target), entry);
                     }
                 }
             }
@@ -187,6 +1350,343 @@ impl Manifest {
         }
     }
 
+    // [dependencies] plus the target-specific ones that apply to `triple`,
+    // either named by it or with a cfg() that holds for it
+    pub fn dependencies_for_triple(&self,
+                                   triple: &str)
+                                   -> Result<Vec<Dependency<'_>>, Vec<PathError>> {
+        let deps = self.get_dependencies()?;
+        Ok(deps.into_iter()
+               .filter(|dep| dep.target.is_none_or(|target| target_applies(target, triple)))
+               .collect())
+    }
+
+    // Only swaps the version if it still reads `expected`, so we don't
+    // overwrite an edit made behind our back
+    pub fn compare_and_set_dependency_version(&mut self,
+                                               name: &str,
+                                               target: Option<&str>,
+                                               expected: &str,
+                                               new: &str)
+                                               -> CasResult {
+        fn swap(value: &mut StringValue, expected: &str, new: &str) -> CasResult {
+            if value.get() == expected {
+                value.set(new.to_owned());
+                CasResult::Updated
+            } else {
+                CasResult::Mismatch(Some(value.get().to_owned()))
+            }
+        }
+        let path = match target {
+            Some(target) => vec!["target", target, "dependencies", name],
+            None => vec!["dependencies", name]
+        };
+        match Manifest::lookup_mut(&mut self.doc, &path) {
+            Some(EntryRefMut::String(value)) => swap(value, expected, new),
+            Some(EntryRefMut::Table(table)) => {
+                match table.get_mut("version") {
+                    Some(EntryRefMut::String(value)) => swap(value, expected, new),
+                    _ => CasResult::Mismatch(None)
+                }
+            }
+            Some(..) => CasResult::Mismatch(None),
+            None => CasResult::Absent
+        }
+    }
+
+    // Adds `name = "version"` to `[dependencies]`, creating the table. If the
+    // dependency is already there only its version changes, a table keeps
+    // its other keys. False if `dependencies` or `name` are something else.
+    pub fn add_dependency(&mut self, name: &str, version: &str) -> bool {
+        let path = ["dependencies", name];
+        let is_table = match Manifest::lookup(&self.doc, &path) {
+            Ok(EntryRef::Table(..)) => true,
+            Ok(EntryRef::String(..)) | Err(QueryError::Vacant { .. }) => false,
+            Ok(..) | Err(QueryError::Conflict { .. }) => return false
+        };
+        if is_table {
+            self.set_string(&["dependencies", name, "version"], version)
+        } else {
+            self.set_string(&path, version)
+        }
+    }
+
+    // Like add_dependency, but false if `name` isn't in `[dependencies]` yet
+    pub fn set_dependency_version(&mut self, name: &str, version: &str) -> bool {
+        match Manifest::lookup(&self.doc, &["dependencies", name]) {
+            Ok(EntryRef::String(..)) | Ok(EntryRef::Table(..)) => self.add_dependency(name, version),
+            _ => false
+        }
+    }
+
+    // An emptied `[dependencies]` stays, so does the `[target.x.dependencies]`
+    // one. False if there was no such dependency.
+    pub fn remove_dependency(&mut self, name: &str, target: Option<&str>) -> bool {
+        let path = match target {
+            Some(target) => vec!["target", target, "dependencies", name],
+            None => vec!["dependencies", name]
+        };
+        self.remove(&path)
+    }
+
+    // Disabling turns `foo = "1"` into `foo = { version = "1", default-features = false }`,
+    // enabling drops the key again and turns an inline table that's left with
+    // only a version back into the plain string. False if there's no such dependency.
+    pub fn set_dependency_default_features(&mut self,
+                                           name: &str,
+                                           target: Option<&str>,
+                                           enabled: bool)
+                                           -> bool {
+        let path = match target {
+            Some(target) => vec!["target", target, "dependencies", name],
+            None => vec!["dependencies", name]
+        };
+        const SPELLINGS: &[&str] = &["default-features", "default_features"];
+        let (version, spelling, present, collapse) = match Manifest::lookup(&self.doc, &path) {
+            Ok(EntryRef::String(version)) => {
+                (Some(version.get().to_owned()), "default-features", Vec::new(), None)
+            }
+            Ok(EntryRef::Table(table)) => {
+                let present = SPELLINGS.iter()
+                                       .cloned()
+                                       .filter(|key| table.get(key).is_some())
+                                       .collect::<Vec<_>>();
+                // Keep the deprecated spelling if that's what's there
+                let spelling = if present.contains(&"default_features") {
+                    "default_features"
+                } else {
+                    "default-features"
+                };
+                let inline = matches!(table.to_value(), TableValue::Inline(..));
+                // Inline table that's down to its version once the key is gone
+                let collapse = match table.get("version") {
+                    Some(EntryRef::String(version))
+                        if inline && !present.is_empty() && table.len() == present.len() + 1 => {
+                        Some(version.get().to_owned())
+                    }
+                    _ => None
+                };
+                (None, spelling, present, collapse)
+            }
+            _ => return false
+        };
+        match (version, enabled) {
+            (Some(..), true) => true,
+            (Some(version), false) => {
+                {
+                    let parent = &path[..path.len() - 1];
+                    let mut table = match self.parent_table_mut(parent) {
+                        Some(table) => table,
+                        None => return false
+                    };
+                    let idx = match table.take_slot(name) {
+                        Some(idx) => idx,
+                        None => return false
+                    };
+                    let dependency = table.insert_inline_table(idx, name);
+                    dependency.insert_string(0, "version", version);
+                    dependency.insert_boolean(1, "default-features", false);
+                }
+                self.restore_header_newlines();
+                true
+            }
+            (None, false) => {
+                let mut key_path = path.clone();
+                key_path.push(spelling);
+                self.set_bool(&key_path, false)
+            }
+            (None, true) if present.is_empty() => true,
+            (None, true) => {
+                {
+                    let parent = match collapse {
+                        Some(..) => &path[..path.len() - 1],
+                        None => &path[..]
+                    };
+                    let mut table = match self.parent_table_mut(parent) {
+                        Some(table) => table,
+                        None => return false
+                    };
+                    match collapse {
+                        Some(version) => {
+                            let idx = match table.take_slot(name) {
+                                Some(idx) => idx,
+                                None => return false
+                            };
+                            table.insert_string(idx, name, &version);
+                        }
+                        None => for key in present {
+                            table.take_slot(key);
+                        }
+                    }
+                }
+                self.restore_header_newlines();
+                true
+            }
+        }
+    }
+
+    // Version Cargo uses for dependency `name`: `workspace = true` is looked up
+    // in `[workspace.dependencies]` of `root`. None if there's no version, also
+    // when `root` doesn't declare the dependency at all.
+    pub fn resolve_dependency_version(&self,
+                                      name: &str,
+                                      root: &Manifest)
+                                      -> Result<Option<String>, Vec<PathError>> {
+        fn error(path: String, expected: &'static str, entry: EntryRef) -> Vec<PathError> {
+            vec![PathError {
+                path,
+                expected,
+                got: entry_kind(entry),
+                severity: Severity::Error
+            }]
+        }
+        fn version(entry: EntryRef, path: String) -> Result<Option<String>, Vec<PathError>> {
+            match entry {
+                EntryRef::String(value) => Ok(Some(value.get().to_owned())),
+                EntryRef::Table(table) => match table.get("version") {
+                    Some(EntryRef::String(value)) => Ok(Some(value.get().to_owned())),
+                    Some(entry) => Err(error(format!("{}.version", path), "string", entry)),
+                    None => Ok(None)
+                },
+                entry => Err(error(path, "string or table", entry))
+            }
+        }
+        let (path, entry) = match self.dependency_tables()
+                                      .into_iter()
+                                      .filter_map(|(path, table)| table.get(name).map(|e| (path, e)))
+                                      .next() {
+            Some((path, entry)) => (format!("{}.{}", path, name), entry),
+            None => return Ok(None)
+        };
+        let inherited = match entry {
+            EntryRef::Table(table) => match table.get("workspace") {
+                Some(EntryRef::Boolean(value)) => value.get(),
+                Some(entry) => return Err(error(format!("{}.workspace", path), "boolean", entry)),
+                None => false
+            },
+            _ => false
+        };
+        if !inherited {
+            return version(entry, path);
+        }
+        match Manifest::lookup(&root.doc, &["workspace", "dependencies", name]) {
+            Ok(entry) => version(entry, format!("workspace.dependencies.{}", name)),
+            Err(..) => Ok(None)
+        }
+    }
+
+    // `workspace = true` dependencies and `[package]` fields that `root` has no
+    // `[workspace.dependencies]` or `[workspace.package]` entry for.
+    pub fn validate_workspace_inheritance(&self, root: &Manifest) -> Vec<PathError> {
+        fn inherits(entry: EntryRef) -> bool {
+            match entry {
+                EntryRef::Table(table) => match table.get("workspace") {
+                    Some(EntryRef::Boolean(value)) => value.get(),
+                    _ => false
+                },
+                _ => false
+            }
+        }
+        let mut errors = Vec::new();
+        for (path, table) in self.dependency_tables() {
+            for (name, entry) in table.iter() {
+                if inherits(entry)
+                   && Manifest::lookup(&root.doc, &["workspace", "dependencies", name]).is_err() {
+                    errors.push(PathError {
+                        path: format!("{}.{}.workspace", path, name),
+                        expected: "[workspace.dependencies] entry in the root",
+                        got: "none",
+                        severity: Severity::Error
+                    });
+                }
+            }
+        }
+        if let Some(EntryRef::Table(package)) = self.doc.get("package") {
+            for (key, entry) in package.iter() {
+                if inherits(entry)
+                   && Manifest::lookup(&root.doc, &["workspace", "package", key]).is_err() {
+                    errors.push(PathError {
+                        path: format!("package.{}.workspace", key),
+                        expected: "[workspace.package] entry in the root",
+                        got: "none",
+                        severity: Severity::Error
+                    });
+                }
+            }
+        }
+        errors
+    }
+
+    // Comments on the line above a dependency and after it on the same line,
+    // for both `name = ...` entries and `[dependencies.name]` headers.
+    // Several comment lines are joined with newlines.
+    pub fn dependency_comments(&self) -> Vec<(String, String)> {
+        fn is_section(keys: &[&str]) -> bool {
+            let section = match keys.len() {
+                1 => keys[0],
+                3 if keys[0] == "target" => keys[2],
+                _ => return false
+            };
+            section == "dependencies" || section == "dev-dependencies"
+            || section == "build-dependencies"
+        }
+        fn push(comments: &mut Vec<(String, String)>, name: &str, lead: &str, trail: &str) {
+            let mut lines = comment_text(lead);
+            lines.extend(comment_text(trail));
+            if !lines.is_empty() {
+                comments.push((name.to_owned(), lines.join("\n")));
+            }
+        }
+        let mut comments = Vec::new();
+        for container in self.doc.iter_containers() {
+            let keys = container.keys().markup().iter().map(|k| k.get()).collect::<Vec<_>>();
+            if is_section(&keys) {
+                for child in container.iter_children() {
+                    push(&mut comments,
+                         child.key().get(),
+                         child.key().get_leading_trivia(),
+                         trailing_trivia(child.value()));
+                }
+            } else if keys.len() > 1 && is_section(&keys[..keys.len() - 1]) {
+                push(&mut comments,
+                     keys[keys.len() - 1],
+                     container.keys().get_leading_trivia(),
+                     container.keys().get_trailing_trivia());
+            }
+        }
+        comments
+    }
+
+    // One edge per entry of the three dependency sections, target-specific
+    // ones included
+    pub fn direct_edges(&self) -> Result<Vec<Edge>, Vec<PathError>> {
+        let mut edges = Vec::new();
+        let mut errors = Vec::new();
+        for &section in &[DependencySection::Normal,
+                          DependencySection::Dev,
+                          DependencySection::Build] {
+            match self.dependencies_in(section) {
+                Ok(deps) => {
+                    edges.extend(deps.iter().map(|dep| {
+                        Edge {
+                            to: dep.name.to_owned(),
+                            requirement: dep.version.map(|v| v.to_owned()),
+                            kind: dep.kind(),
+                            section,
+                            target: dep.target.map(|t| t.to_owned())
+                        }
+                    }))
+                }
+                Err(mut errs) => errors.append(&mut errs)
+            }
+        }
+        if errors.is_empty() {
+            Ok(edges)
+        } else {
+            Err(errors)
+        }
+    }
+
     pub fn get_output_targets(&self) -> Result<Vec<OutputTarget>, Vec<PathError>> {
         fn get_string<'a>(entry: Option<EntryRef<'a>>,
                           path: String)
@@ -197,7 +1697,8 @@ impl Manifest {
                     let error = PathError {
                         path: path,
                         expected: "string",
-                        got: entry_kind(entry)
+                        got: entry_kind(entry),
+                        severity: Severity::Error
                     };
                     Err(error)
                 }
@@ -213,7 +1714,8 @@ impl Manifest {
                     let error = PathError {
                         path: path,
                         expected: "boolean",
-                        got: entry_kind(entry)
+                        got: entry_kind(entry),
+                        severity: Severity::Error
                     };
                     Err(error)
                 }
@@ -226,12 +1728,31 @@ impl Manifest {
                          -> Result<OutputTarget<'a>, PathError> {
             target.name = try!(get_string(entry.get("name"), format!("{}.name", src)));
             target.path = try!(get_string(entry.get("path"), format!("{}.path", src)));
+            target.edition = get_string(entry.get("edition"), format!("{}.edition", src))?;
             target.test = try!(get_bool(entry.get("test"), format!("{}.test", src)));
             target.doctest = try!(get_bool(entry.get("doctest"), format!("{}.doctest", src)));
             target.bench = try!(get_bool(entry.get("bench"), format!("{}.bench", src)));
             target.doc = try!(get_bool(entry.get("doc"), format!("{}.doc", src)));
             target.plugin = try!(get_bool(entry.get("plugin"), format!("{}.plugin", src)));
             target.harness= try!(get_bool(entry.get("harness"), format!("{}.harness", src)));
+            target.required_features = get_string_array_entry(entry.get("required-features"),
+                                                              format!("{}.required-features",
+                                                                      src))?;
+            if target.kind == "lib" {
+                let proc_macro = get_bool(entry.get("proc-macro"),
+                                          format!("{}.proc-macro", src))?;
+                target.proc_macro = proc_macro.unwrap_or(false);
+                // `crate_type` is the old spelling, it used to take a single string too
+                target.crate_type = match (entry.get("crate-type"), entry.get("crate_type")) {
+                    (None, Some(EntryRef::String(crate_type))) => vec![crate_type.get()],
+                    (None, legacy @ Some(..)) => {
+                        get_string_array_entry(legacy, format!("{}.crate_type", src))?
+                    }
+                    (entry, _) => {
+                        get_string_array_entry(entry, format!("{}.crate-type", src))?
+                    }
+                };
+            }
             Ok(target)
         }
         fn get_table<'a, F>(src: &'a str,
@@ -263,7 +1784,8 @@ impl Manifest {
                     let error = PathError {
                         path: src.to_owned(),
                         expected: "table",
-                        got: entry_kind(entry)
+                        got: entry_kind(entry),
+                        severity: Severity::Error
                     };
                     errors.push(error);
                 }
@@ -283,7 +1805,8 @@ impl Manifest {
                         let error = PathError {
                             path: src.to_owned(),
                             expected: "array of tables",
-                            got: kind.unwrap()
+                            got: kind.unwrap(),
+                            severity: Severity::Error
                         };
                         errors.push(error);
                         return;
@@ -296,7 +1819,8 @@ impl Manifest {
                     let error = PathError {
                         path: src.to_owned(),
                         expected: "array",
-                        got: entry_kind(entry)
+                        got: entry_kind(entry),
+                        severity: Severity::Error
                     };
                     errors.push(error);
                 }
@@ -321,252 +1845,3196 @@ impl Manifest {
         }
     }
 
-    fn lookup<'a>(doc: &'a Document,path: &'a [&'a str]) -> Result<EntryRef<'a>, QueryError> {
-        fn lookup_inner<'a>(entry: EntryRef<'a>,
-                            path: &'a [&'a str],
-                            depth: usize)
-                            -> Result<EntryRef<'a>, QueryError> {
-            if path.len() == 0 {
-                Ok(entry)
-            } else {
-                match entry {
-                    EntryRef::Table(table) => {
-                        table.get(path[0])
-                             .map_or_else(|| Err(QueryError::Vacant{ depth: depth }),
-                                          |e| lookup_inner(e, &path[1..], depth + 1))
-                    }
-                    _ => {
-                        Err(QueryError::Conflict { depth: depth, kind: entry_kind(entry) })
-                    }
+    // Declared targets only, the ones Cargo would discover on disk aren't here.
+    // Lib name gets its hyphens replaced like Cargo does for the crate name.
+    pub fn build_units(&self) -> Result<Vec<BuildUnit>, Vec<PathError>> {
+        fn get_edition<'a>(entry: Option<EntryRef<'a>>,
+                           path: String,
+                           errors: &mut Vec<PathError>)
+                           -> Option<&'a str> {
+            match entry {
+                Some(EntryRef::String(edition)) => Some(edition.get()),
+                Some(entry) => {
+                    errors.push(PathError {
+                        path,
+                        expected: "string",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    });
+                    None
                 }
+                None => None
             }
         }
-        doc.get(path[0])
-           .map_or(Err(QueryError::Vacant{ depth: 0 }),
-                   |entry| lookup_inner(entry, &path[1..], 0))
-    }
-
-    fn add_output_target(&mut self, target: OutputTarget) -> usize {
-        let kind = if target.kind == "lib" {
-            ContainerKind::Table
+        let targets = self.get_output_targets()?;
+        let mut errors = Vec::new();
+        let package_name = self.get_string(&["package", "name"]).unwrap_or("");
+        let package_edition = self.doc.get("package").and_then(|package| match package {
+            EntryRef::Table(package) => {
+                get_edition(package.get("edition"), "package.edition".to_owned(), &mut errors)
+            }
+            _ => None
+        });
+        let mut units = Vec::with_capacity(targets.len());
+        for target in &targets {
+            let name = target.name.unwrap_or(package_name);
+            let name = if target.kind == "lib" { name.replace('-', "_") } else { name.to_owned() };
+            units.push(BuildUnit {
+                kind: target.kind.to_owned(),
+                name,
+                effective_path: target.effective_path(package_name),
+                required_features: target.required_features.iter().map(|f| f.to_string()).collect(),
+                edition: target.edition.or(package_edition).unwrap_or("2015").to_owned()
+            });
+        }
+        if !errors.is_empty() {
+            Err(errors)
         } else {
-            ContainerKind::ArrayMember
-        };
-        let index = self.doc.len();
-        let mut container = self.doc.insert_container(index, iter::once(target.kind), kind);
-        Manifest::add_output_target_inner(&mut container, target);
-        container.ptr()
+            Ok(units)
+        }
     }
 
-    fn add_output_target_inner(container: &mut Container, target: OutputTarget) {
-        fn append_bool(cnt: &mut Container, key: &'static str, value: Option<bool>) {
-            if let Some(value) = value {
-                let index = cnt.len_children();
-                cnt.insert_boolean(index, key, value);
+    // `package.edition` first, then whatever the targets override it with
+    pub fn all_editions(&self) -> Result<Vec<&str>, Vec<PathError>> {
+        fn add<'a>(entry: Option<EntryRef<'a>>,
+                   path: String,
+                   editions: &mut Vec<&'a str>,
+                   errors: &mut Vec<PathError>) {
+            match entry {
+                Some(EntryRef::String(edition)) if !editions.contains(&edition.get()) => {
+                    editions.push(edition.get());
+                }
+                Some(EntryRef::String(..)) => {}
+                Some(entry) => {
+                    let error = PathError {
+                        path,
+                        expected: "string",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    };
+                    errors.push(error);
+                }
+                None => {}
             }
         }
-        fn append_string<'a>(cnt: &mut Container, key: &'static str, value: Option<&'a str>) {
-            if let Some(value) = value {
-                let index = cnt.len_children();
-                cnt.insert_string(index, key, value);
-            }
+        let mut editions = Vec::new();
+        let mut errors = Vec::new();
+        if let Some(EntryRef::Table(package)) = self.doc.get("package") {
+            add(package.get("edition"), "package.edition".to_owned(), &mut editions, &mut errors);
         }
-        set_output_target!(container, target, append_string, append_bool);
-    }
-
-    fn set_output_target(&mut self, target: OutputTarget) -> Option<usize> {
-        if target.handle == 0 {
-            let length = self.doc.len();
-            let container = self.doc.insert_container(length,
-                                                      iter::once(target.kind),
-                                                      ContainerKind::Table);
-            Manifest::set_output_target_inner(container, target);
-            Some(container.ptr())
-        } else {
-            let cursor = NodeCursor(target.handle);
-            let maybe_index = self.doc.find(&cursor);
-            match maybe_index {
-                Some(index) => {
-                    if index < self.doc.len_children() {
-                        let child = self.doc.get_child_mut(index);
-                        if let ValueRefMut::Table(table) = child.value_mut() {
-                            Manifest::set_output_target_inline_table(table, target);
-                        } else {
-                            unreachable!()
-                        }
-                    } else {
-                        let children_len = self.doc.len_children();
-                        let container = self.doc.get_container_mut(index - children_len);
-                        Manifest::set_output_target_inner(container, target);
-                    }
+        for kind in &["lib", "bin", "bench", "test", "example"] {
+            let path = format!("{}.edition", kind);
+            match self.doc.get(kind) {
+                Some(EntryRef::Table(table)) => {
+                    add(table.get("edition"), path, &mut editions, &mut errors);
                 }
-                None => {
-                    match self.doc.get_mut(target.kind) {
-                        Some(EntryRefMut::Array(array)) => {
-                            match array.to_value() {
-                                ArrayValueMut::Inline(mut inline_array) => {
-                                    Manifest::set_output_target_inline_array(inline_array, target);
-                                }
-                                ArrayValueMut::OfTables => unreachable!()
-                            }
+                Some(EntryRef::Array(array)) => {
+                    for entry in array.iter() {
+                        if let EntryRef::Table(table) = entry {
+                            add(table.get("edition"), path.clone(), &mut editions, &mut errors);
                         }
-                        _ => unreachable!()
                     }
                 }
+                _ => {}
             }
-            None
+        }
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(editions)
         }
     }
 
-    fn set_output_target_inner(container: &mut Container, target: OutputTarget) {
-        fn set_bool(cnt: &mut Container, key: &'static str, value: Option<bool>) {
-            if let Some(value) = value {
-                Manifest::remove_child(cnt, key);
-                let index = cnt.len_children();
-                cnt.insert_boolean(index, key, value);
+    // Snapshot that outlives the manifest. Sections that don't read cleanly
+    // come out empty, `validate` is the place to learn why.
+    pub fn to_owned_model(&self) -> OwnedManifest {
+        fn string(manifest: &Manifest, key: &str) -> Option<String> {
+            manifest.get_string(&["package", key]).ok().map(|value| value.to_owned())
+        }
+        let package = match self.doc.get("package") {
+            Some(EntryRef::Table(..)) => {
+                let authors = self.get_string_array(&["package", "authors"]).unwrap_or_default();
+                Some(OwnedPackage {
+                    name: string(self, "name"),
+                    version: string(self, "version"),
+                    authors: authors.iter().map(|author| author.to_string()).collect(),
+                    description: string(self, "description"),
+                    license: string(self, "license"),
+                    edition: string(self, "edition")
+                })
             }
+            _ => None
+        };
+        let features = self.get_features().unwrap_or_default();
+        OwnedManifest {
+            package,
+            dependencies: self.get_dependencies()
+                              .unwrap_or_default()
+                              .iter()
+                              .map(OwnedDependency::from)
+                              .collect(),
+            output_targets: self.get_output_targets()
+                                .unwrap_or_default()
+                                .iter()
+                                .map(OwnedOutputTarget::from)
+                                .collect(),
+            features: features.iter()
+                              .map(|&(name, ref enables)| {
+                                  (name.to_owned(), enables.iter().map(|e| e.to_string()).collect())
+                              })
+                              .collect()
         }
-        fn set_string<'a>(cnt: &mut Container, key: &'static str, value: Option<&'a str>) {
-            if let Some(value) = value {
-                Manifest::remove_child(cnt, key);
-                let index = cnt.len_children();
-                cnt.insert_string(index, key, value);
+    }
+
+    pub fn crate_kinds(&self) -> CrateKinds {
+        fn auto(manifest: &Manifest, key: &'static str) -> bool {
+            match Manifest::lookup(&manifest.doc, &["package", key]) {
+                Ok(EntryRef::Boolean(value)) => value.get(),
+                Ok(..) => true,
+                Err(..) => manifest.doc.get("package").is_some()
             }
         }
-        set_output_target!(container, target, set_string, set_bool);
+        let has_bins = match self.doc.get("bin") {
+            Some(EntryRef::Array(array)) => array.len() > 0,
+            _ => false
+        };
+        CrateKinds {
+            has_lib: self.doc.get("lib").is_some(),
+            has_bins,
+            auto_lib: auto(self, "autolib"),
+            auto_bins: auto(self, "autobins")
+        }
     }
 
-    fn set_output_target_inline_array(inline_array: &mut InlineArray, target: OutputTarget) {
-        let idx = inline_array.find(&NodeCursor(target.handle)).unwrap();
-        let value = inline_array.get_mut(idx);
-        let table = if let ValueRefMut::Table(table) = value {
-            table
-        } else {
-            panic!("Invalid operation");
+    // Binary Cargo builds from src/main.rs when no `[[bin]]` is given,
+    // provided the file exists which is up to the caller to check
+    pub fn default_bin_name(&self) -> Result<Option<&str>, QueryError> {
+        let kinds = self.crate_kinds();
+        if kinds.has_bins() || !kinds.auto_bins() {
+            return Ok(None);
+        }
+        match self.get_string(&["package", "name"]) {
+            Ok(name) => Ok(Some(name)),
+            Err(QueryError::Vacant { .. }) => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+
+    // Features in document order, each with the list of things it enables
+    pub fn get_features(&self) -> Result<Vec<(&str, Vec<&str>)>, Vec<PathError>> {
+        let table = match self.doc.get("features") {
+            Some(EntryRef::Table(table)) => table,
+            Some(entry) => {
+                let error = PathError {
+                    path: "features".to_owned(),
+                    expected: "table",
+                    got: entry_kind(entry),
+                    severity: Severity::Error
+                };
+                return Err(vec![error]);
+            }
+            None => return Ok(Vec::new())
         };
-        Manifest::set_output_target_inline_table(table, target);
+        let mut features = Vec::new();
+        let mut errors = Vec::new();
+        for name in self.ordered_keys(&["features"]) {
+            match get_string_array_entry(table.get(name), format!("features.{}", name)) {
+                Ok(enables) => features.push((name, enables)),
+                Err(error) => errors.push(error)
+            }
+        }
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(features)
+        }
     }
 
-    fn set_output_target_inline_table(table: &mut InlineTable, target: OutputTarget) {
-        fn set_bool(table: &mut InlineTable, key: &'static str, value: Option<bool>) {
-            if let Some(value) = value {
-                Manifest::remove_child_inline(table, key);
-                let index = table.len();
-                table.insert_boolean(index, key, value);
+    // `[[bin]]`, `[[bench]]`, `[[test]]` and `[[example]]` with how many of each
+    // there are. Kinds without entries are left out.
+    pub fn target_section_counts(&self) -> Vec<(&'static str, usize)> {
+        const KINDS: &[&str] = &["bin", "bench", "test", "example"];
+        KINDS.iter()
+             .filter_map(|kind| match self.doc.get(kind) {
+                 Some(EntryRef::Array(array)) if array.len() > 0 => Some((*kind, array.len())),
+                 _ => None
+             })
+             .collect()
+    }
+
+    // Features `default` turns on, directly or through other features, in the
+    // order they're reached. `dep:x` and `x/y` entries aren't features.
+    pub fn default_feature_closure(&self) -> Result<Vec<String>, Vec<PathError>> {
+        let features = self.get_features()?;
+        Ok(Manifest::feature_closure(&features, "default"))
+    }
+
+    // Same as building with `--features` set to `enabled`,
+    // and `--no-default-features` unless `default_features`. Like Cargo,
+    // `default` is only implied when the manifest declares it.
+    pub fn is_feature_active(&self,
+                             feature: &str,
+                             enabled: &[&str],
+                             default_features: bool)
+                             -> Result<bool, Vec<PathError>> {
+        let features = self.get_features()?;
+        let mut seeds = enabled.to_vec();
+        if default_features && features.iter().any(|&(name, _)| name == "default") {
+            seeds.push("default");
+        }
+        Ok(seeds.iter().any(|seed| {
+            *seed == feature || Manifest::feature_closure(&features, seed).iter().any(|f| f == feature)
+        }))
+    }
+
+    // Renames the feature and every bare reference to it in the other features,
+    // returns how many references were updated. `dep:` and `crate/feature`
+    // entries are about dependencies, so they stay. Needs a `[features]` header.
+    // The key is renamed in the source and reparsed, target handles carry over.
+    pub fn rename_feature(&mut self, old: &str, new: &str) -> Result<usize, Vec<PathError>> {
+        let references = {
+            let features = self.get_features()?;
+            if features.iter().any(|&(name, _)| name == new) {
+                let error = PathError {
+                    path: format!("features.{}", new),
+                    expected: "unused feature name",
+                    got: "existing feature",
+                    severity: Severity::Error
+                };
+                return Err(vec![error]);
             }
+            if !features.iter().any(|&(name, _)| name == old) {
+                return Ok(0);
+            }
+            features.iter()
+                    .filter(|&&(name, _)| name != old)
+                    .map(|&(name, ref enables)| {
+                        let indices = enables.iter()
+                                             .enumerate()
+                                             .filter(|&(_, enabled)| *enabled == old)
+                                             .map(|(idx, _)| idx)
+                                             .collect::<Vec<_>>();
+                        (name.to_owned(), indices)
+                    })
+                    .filter(|(_, indices)| !indices.is_empty())
+                    .collect::<Vec<_>>()
+        };
+        if !self.rename_key("features", old, new) {
+            let error = PathError {
+                path: "features".to_owned(),
+                expected: "[features] table",
+                got: "inline table",
+                severity: Severity::Error
+            };
+            return Err(vec![error]);
         }
-        fn set_string<'a>(table: &mut InlineTable, key: &'static str, value: Option<&'a str>) {
-            if let Some(value) = value {
-                Manifest::remove_child_inline(table, key);
-                let index = table.len();
-                table.insert_string(index, key, value);
+        let mut updated = 0;
+        for (name, indices) in references {
+            let path = ["features", &name];
+            if let Some(EntryRefMut::Array(array)) = Manifest::lookup_mut(&mut self.doc, &path) {
+                if let ArrayValueMut::Inline(array) = array.to_value() {
+                    for idx in indices {
+                        if let ValueRefMut::String(value) = array.get_mut(idx) {
+                            value.set(new.to_owned());
+                            updated += 1;
+                        }
+                    }
+                }
             }
         }
-        set_output_target!(table, target, set_string, set_bool);
+        Ok(updated)
     }
 
-    fn remove_output_target(&mut self, handle: usize, kind: &str) {
-        fn remove_child(doc: &mut Document, key: &str) {
-            if let Some(idx) = doc.iter_children().position(|c| key == c.key().get()) {
-                doc.remove(idx)
+    // False for a virtual manifest, one with only `[workspace]`
+    pub fn has_package(&self) -> bool {
+        matches!(self.doc.get("package"), Some(EntryRef::Table(..)))
+    }
+
+    // None for a virtual manifest, see has_package
+    pub fn package(&self) -> Option<PackageView<'_>> {
+        if self.has_package() {
+            Some(PackageView { manifest: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn error_count(&self, section: Section) -> usize {
+        fn count<T>(result: Result<T, Vec<PathError>>) -> usize {
+            result.err().map_or(0, |errors| errors.len())
+        }
+        match section {
+            Section::Dependencies => count(self.get_dependencies()),
+            Section::Targets => count(self.get_output_targets()),
+            Section::Package => self.package_errors().len(),
+            Section::Features => count(self.get_features()),
+            Section::Profiles => self.profile_errors().len(),
+        }
+    }
+
+    // Everything the per-section readers report plus the lints below
+    pub fn validate(&self) -> Vec<PathError> {
+        let mut errors = Vec::new();
+        if let Err(errs) = self.get_dependencies() {
+            errors.extend(errs);
+        }
+        if let Err(errs) = self.get_output_targets() {
+            errors.extend(errs);
+        }
+        errors.extend(self.package_errors());
+        if let Err(errs) = self.get_features() {
+            errors.extend(errs);
+        }
+        errors.extend(self.profile_errors());
+        if let Err(errs) = self.get_workspace() {
+            errors.extend(errs);
+        }
+        errors.extend(self.lints());
+        errors
+    }
+
+    // Things Cargo accepts but the IDE wants to point out
+    fn lints(&self) -> Vec<PathError> {
+        let mut lints = Vec::new();
+        if let Ok("0.0.0") = self.get_string(&["package", "version"]) {
+            lints.push(PathError {
+                path: "package.version".to_owned(),
+                expected: "non-placeholder version",
+                got: "0.0.0",
+                severity: Severity::Warning
+            });
+        }
+        // A bare name can only enable a feature or an optional dependency,
+        // features of a regular dependency need the `dependency/feature` form.
+        // Features can't reach dev-dependencies, so those names don't count.
+        if let (Ok(features), Ok(optional)) = (self.get_features(), self.optional_dependencies()) {
+            let dependencies = self.dependency_tables()
+                                   .into_iter()
+                                   .filter(|(path, _)| !path.ends_with("dev-dependencies"))
+                                   .flat_map(|(_, table)| table.iter().map(|(name, _)| name))
+                                   .collect::<Vec<_>>();
+            for &(feature, ref enables) in features.iter() {
+                for name in enables {
+                    if !features.iter().any(|&(other, _)| other == *name)
+                       && !optional.contains(name)
+                       && dependencies.contains(name) {
+                        lints.push(PathError {
+                            path: format!("features.{}.{}", feature, name),
+                            expected: "dependency/feature",
+                            got: "non-optional dependency",
+                            severity: Severity::Error
+                        });
+                    }
+                }
             }
         }
-        if handle == 0 {
-            remove_child(&mut self.doc, kind);
-            Manifest::remove_containers(&mut self.doc, iter::once(kind));
-        } else {
-            let position = self.doc.find(&NodeCursor(handle)).unwrap();
-            self.doc.remove(position);
+        // Cargo requires a build script with `links`. Without `build` it picks up
+        // build.rs, which we can't check from here.
+        if self.declares_native_link() {
+            if let Ok(BuildScript::Disabled) = self.get_build_script() {
+                lints.push(PathError {
+                    path: "package.links".to_owned(),
+                    expected: "build script",
+                    got: "build = false",
+                    severity: Severity::Warning
+                });
+            }
+        }
+        lints.extend(self.validate_license_spdx());
+        // Cargo turns hyphens of the package name into underscores for the
+        // lib crate, but an explicit `[lib] name` has to be valid as written
+        let has_lib = self.doc.get("lib").is_some() || self.crate_kinds().auto_lib();
+        let lib_name = match self.get_string(&["lib", "name"]) {
+            Ok(name) => Some(("lib.name", name.to_owned())),
+            Err(QueryError::Vacant { .. }) if has_lib => {
+                self.get_string(&["package", "name"])
+                    .ok()
+                    .map(|name| ("package.name", name.replace('-', "_")))
+            }
+            Err(..) => None
+        };
+        if let Some((path, name)) = lib_name {
+            let got = if name.is_empty() {
+                Some("empty string")
+            } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+                Some("leading digit")
+            } else if name.contains('-') {
+                Some("hyphen")
+            } else if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                Some("invalid character")
+            } else {
+                None
+            };
+            if let Some(got) = got {
+                lints.push(PathError {
+                    path: path.to_owned(),
+                    expected: "crate name",
+                    got,
+                    severity: Severity::Error
+                });
+            }
+        }
+        for (path, table) in self.dependency_tables() {
+            for (name, entry) in table.iter() {
+                if let EntryRef::Table(dep) = entry {
+                    if dep.get("default_features").is_some() {
+                        lints.push(PathError {
+                            path: format!("{}.{}.default_features", path, name),
+                            expected: "default-features",
+                            got: "default_features",
+                            severity: Severity::Info
+                        });
+                    }
+                }
+            }
         }
+        lints
     }
 
-    fn remove_child(cnt: &mut Container, key: &str) {
-        if let Some(idx) = cnt.iter_children().position(|c| key == c.key().get()) {
-            cnt.remove(idx)
+    // Only the structure of the expression, None if `license` isn't a string.
+    // crates.io still accepts `MIT/Apache-2.0`, so this is a warning.
+    pub fn validate_license_spdx(&self) -> Option<PathError> {
+        let license = match self.get_string(&["package", "license"]) {
+            Ok(license) => license,
+            Err(..) => return None
+        };
+        if spdx_is_valid(license) {
+            return None;
         }
+        Some(PathError {
+            path: "package.license".to_owned(),
+            expected: "SPDX expression",
+            got: if license.contains('/') { "`/` separator" } else { "malformed expression" },
+            severity: Severity::Warning
+        })
     }
 
-    fn remove_child_inline(table: &mut InlineTable, key: &str) {
-        if let Some(idx) = table.iter().position(|c| key == c.key().get()) {
-            table.remove(idx)
+    // Only checks types of the well-known fields, Cargo does the rest.
+    // Nothing is missing from a manifest without `[package]`.
+    fn package_errors(&self) -> Vec<PathError> {
+        const STRINGS: &[&str] = &["name", "version", "description",
+                                                   "documentation", "homepage", "repository",
+                                                   "license", "license-file", "edition",
+                                                   "links", "workspace"];
+        const ARRAYS: &[&str] = &["authors", "keywords", "categories",
+                                                  "include", "exclude"];
+        let table = match self.doc.get("package") {
+            Some(EntryRef::Table(table)) => table,
+            Some(entry) => {
+                let error = PathError {
+                    path: "package".to_owned(),
+                    expected: "table",
+                    got: entry_kind(entry),
+                    severity: Severity::Error
+                };
+                return vec![error];
+            }
+            None => return Vec::new()
+        };
+        let mut errors = Vec::new();
+        for key in STRINGS {
+            match table.get(key) {
+                Some(EntryRef::String(..)) | None => {}
+                // `edition = 2021` is the usual mistake, say how to fix it
+                Some(entry @ EntryRef::Integer(..)) if *key == "edition" => {
+                    let error = PathError {
+                        path: "package.edition".to_owned(),
+                        expected: "string, eg. \"2021\" in quotes",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    };
+                    errors.push(error);
+                }
+                Some(entry) => {
+                    let error = PathError {
+                        path: format!("package.{}", key),
+                        expected: "string",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    };
+                    errors.push(error);
+                }
+            }
+        }
+        for key in ARRAYS {
+            if let Err(error) = get_string_array_entry(table.get(key), format!("package.{}", key)) {
+                errors.push(error);
+            }
         }
+        errors
     }
 
-    fn remove_containers<'a, I:Iterator<Item=&'a str>+Clone>(doc: &mut Document, keys: I) {
-        fn find_container<'a, I>(doc: &mut Document, keys: I)
-                                -> Option<usize> where I:Iterator<Item=&'a str>+Clone {
-            doc.iter_containers().position(|c| {
-                c.keys().markup().iter().zip(keys.clone()).all(|(m, k)| m.get() == k)
-            })
+    fn profile_errors(&self) -> Vec<PathError> {
+        match self.doc.get("profile") {
+            Some(EntryRef::Table(table)) => {
+                table.iter()
+                     .filter_map(|(name, entry)| match entry {
+                         EntryRef::Table(..) => None,
+                         entry => Some(PathError {
+                             path: format!("profile.{}", name),
+                             expected: "table",
+                             got: entry_kind(entry),
+                             severity: Severity::Error
+                         })
+                     })
+                     .collect()
+            }
+            Some(entry) => {
+                vec![PathError {
+                    path: "profile".to_owned(),
+                    expected: "table",
+                    got: entry_kind(entry),
+                    severity: Severity::Error
+                }]
+            }
+            None => Vec::new()
         }
+    }
+
+    // `[profile.release.package.foo]` and the like only add to `release`
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.ordered_keys(&["profile"])
+    }
+
+    // `panic` of `profile` or the profile it inherits from, `test` and `bench`
+    // fall back to `dev` and `release` like they do in Cargo. None if nothing
+    // along the way sets it.
+    pub fn effective_panic(&self, profile: &str) -> Result<Option<&str>, Vec<PathError>> {
+        fn error(path: String, expected: &'static str, got: &'static str) -> Vec<PathError> {
+            vec![PathError {
+                path,
+                expected,
+                got,
+                severity: Severity::Error
+            }]
+        }
+        let profiles = match self.doc.get("profile") {
+            Some(EntryRef::Table(table)) => table,
+            Some(..) => return Err(self.profile_errors()),
+            None => return Ok(None)
+        };
+        // Lookups need keys that live as long as the document
+        let names = self.ordered_keys(&["profile"]);
+        let mut visited = Vec::new();
+        let mut current = profile.to_owned();
         loop {
-            let position = find_container(doc, keys.clone());
-            match position {
-                Some(position) => {
-                    let len_children = doc.len_children();
-                    doc.remove(position + len_children);
+            if visited.contains(&current) {
+                let path = format!("profile.{}.inherits", current);
+                return Err(error(path, "profile that doesn't inherit back", "cycle"));
+            }
+            let table = match names.iter().find(|name| **name == current) {
+                Some(name) => match profiles.get(name) {
+                    Some(EntryRef::Table(table)) => Some(table),
+                    Some(entry) => {
+                        let path = format!("profile.{}", current);
+                        return Err(error(path, "table", entry_kind(entry)));
+                    }
+                    None => None
+                },
+                None => None
+            };
+            if let Some(table) = table {
+                match table.get("panic") {
+                    Some(EntryRef::String(value)) => return Ok(Some(value.get())),
+                    Some(entry) => {
+                        let path = format!("profile.{}.panic", current);
+                        return Err(error(path, "string", entry_kind(entry)));
+                    }
+                    None => {}
                 }
-                None => break
             }
+            let parent = match table.and_then(|table| table.get("inherits")) {
+                Some(EntryRef::String(value)) => value.get().to_owned(),
+                Some(entry) => {
+                    let path = format!("profile.{}.inherits", current);
+                    return Err(error(path, "string", entry_kind(entry)));
+                }
+                None => match &*current {
+                    "test" => "dev".to_owned(),
+                    "bench" => "release".to_owned(),
+                    _ => return Ok(None)
+                }
+            };
+            visited.push(current);
+            current = parent;
         }
     }
-}
-impl std::panic::RefUnwindSafe for Manifest { }
 
-struct NodeCursor(usize);
-impl InternalNode for NodeCursor {
-    fn ptr(&self) -> usize {
-        self.0
+    pub fn get_readme(&self) -> Result<Readme<'_>, QueryError> {
+        match Manifest::lookup(&self.doc, &["package", "readme"]) {
+            Ok(EntryRef::String(value)) => Ok(Readme::Path(value.get())),
+            Ok(EntryRef::Boolean(value)) if !value.get() => Ok(Readme::Disabled),
+            // `readme = true` asks for the README.md Cargo would find anyway
+            Ok(EntryRef::Boolean(..)) => Ok(Readme::Default),
+            Ok(entry) => Err(QueryError::conflict(&["package", "readme"], entry_kind(entry))),
+            Err(QueryError::Vacant { .. }) => Ok(Readme::Default),
+            Err(err) => Err(err)
+        }
     }
-}
 
+    // Readme::Default removes the key. Fails if `package` isn't a `[package]` table.
+    pub fn set_readme(&mut self, readme: Readme) -> bool {
+        let cnt = match self.table_container_mut(&["package"]) {
+            Some(cnt) => cnt,
+            None => return false
+        };
+        let index = match cnt.iter_children().position(|c| c.key().get() == "readme") {
+            Some(index) => {
+                cnt.remove(index);
+                index
+            }
+            None => cnt.len_children()
+        };
+        match readme {
+            Readme::Path(path) => { cnt.insert_string(index, "readme", path); }
+            Readme::Disabled => { cnt.insert_boolean(index, "readme", false); }
+            Readme::Default => {}
+        }
+        self.restore_header_newlines();
+        true
+    }
 
+    // Manifest path and value of every string Cargo reads as a file path:
+    // `build`, `readme`, `license-file`, `include`/`exclude` patterns, target
+    // `path`s and `path` dependencies. Array elements get their index as key.
+    // Entries of the wrong type are skipped, the section readers report them.
+    pub fn referenced_paths(&self) -> Vec<(Vec<String>, &str)> {
+        fn owned(path: &[&str]) -> Vec<String> {
+            path.iter().map(|key| key.to_string()).collect()
+        }
+        const SECTIONS: &[&str] = &["dependencies",
+                                                    "dev-dependencies",
+                                                    "build-dependencies"];
+        let mut paths = Vec::new();
+        if let Some(EntryRef::Table(package)) = self.doc.get("package") {
+            for key in &["build", "readme", "license-file"] {
+                if let Some(EntryRef::String(value)) = package.get(key) {
+                    paths.push((owned(&["package", *key]), value.get()));
+                }
+            }
+            for key in &["include", "exclude"] {
+                if let Some(EntryRef::Array(array)) = package.get(key) {
+                    for (idx, entry) in array.iter().enumerate() {
+                        if let EntryRef::String(value) = entry {
+                            paths.push((owned(&["package", *key, &idx.to_string()]), value.get()));
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(EntryRef::Table(lib)) = self.doc.get("lib") {
+            if let Some(EntryRef::String(value)) = lib.get("path") {
+                paths.push((owned(&["lib", "path"]), value.get()));
+            }
+        }
+        for kind in &["bin", "example", "test", "bench"] {
+            if let Some(EntryRef::Array(array)) = self.doc.get(kind) {
+                for (idx, entry) in array.iter().enumerate() {
+                    if let EntryRef::Table(table) = entry {
+                        if let Some(EntryRef::String(value)) = table.get("path") {
+                            paths.push((owned(&[*kind, &idx.to_string(), "path"]), value.get()));
+                        }
+                    }
+                }
+            }
+        }
+        // Not dependency_tables, its dotted paths can't be split back up
+        // when a `cfg(..)` key has dots in it
+        let mut tables = Vec::new();
+        for section in SECTIONS {
+            if let Some(EntryRef::Table(table)) = self.doc.get(section) {
+                tables.push((vec![*section], table));
+            }
+        }
+        if let Some(EntryRef::Table(targets)) = self.doc.get("target") {
+            for (target, target_entry) in targets.iter() {
+                if let EntryRef::Table(target_table) = target_entry {
+                    for section in SECTIONS {
+                        if let Some(EntryRef::Table(table)) = target_table.get(section) {
+                            tables.push((vec!["target", target, *section], table));
+                        }
+                    }
+                }
+            }
+        }
+        for (prefix, table) in tables {
+            for (name, entry) in table.iter() {
+                if let EntryRef::Table(dep) = entry {
+                    if let Some(EntryRef::String(value)) = dep.get("path") {
+                        let mut path = owned(&prefix);
+                        path.push(name.to_owned());
+                        path.push("path".to_owned());
+                        paths.push((path, value.get()));
+                    }
+                }
+            }
+        }
+        paths
+    }
 
-pub enum QueryError {
-    Vacant{ depth: usize },
-    Conflict{ depth: usize, kind: &'static str }
-}
+    pub fn get_build_script(&self) -> Result<BuildScript<'_>, QueryError> {
+        match Manifest::lookup(&self.doc, &["package", "build"]) {
+            Ok(EntryRef::String(value)) => Ok(BuildScript::Path(value.get())),
+            Ok(EntryRef::Boolean(value)) if !value.get() => Ok(BuildScript::Disabled),
+            // `build = true` means build.rs, same as leaving it out
+            Ok(EntryRef::Boolean(..)) => Ok(BuildScript::Default),
+            Ok(entry) => Err(QueryError::conflict(&["package", "build"], entry_kind(entry))),
+            Err(QueryError::Vacant { .. }) => Ok(BuildScript::Default),
+            Err(err) => Err(err)
+        }
+    }
 
-pub struct Dependency<'a> {
-    name: &'a str,
-    version: Option<&'a str>,
-    git: Option<&'a str>,
-    path: Option<&'a str>,
-    target: Option<&'a str>
-}
+    pub fn declares_native_link(&self) -> bool {
+        self.get_string(&["package", "links"]).is_ok()
+    }
 
-impl<'a> Dependency<'a> {
-    fn simple(name: &'a str, target: Option<&'a str>, version: &'a str) -> Dependency<'a> {
-        Dependency {
-            name: name,
-            version: Some(version),
-            git: None,
-            path: None,
-            target: target
+    pub fn get_workspace(&self) -> Result<Option<Workspace<'_>>, Vec<PathError>> {
+        let table = match self.doc.get("workspace") {
+            Some(EntryRef::Table(table)) => table,
+            Some(entry) => {
+                let error = PathError {
+                    path: "workspace".to_owned(),
+                    expected: "table",
+                    got: entry_kind(entry),
+                    severity: Severity::Error
+                };
+                return Err(vec![error]);
+            }
+            None => return Ok(None)
+        };
+        let mut errors = Vec::new();
+        let members = get_string_array_entry(table.get("members"), "workspace.members".to_owned())
+                          .unwrap_or_else(|e| { errors.push(e); Vec::new() });
+        let exclude = get_string_array_entry(table.get("exclude"), "workspace.exclude".to_owned())
+                          .unwrap_or_else(|e| { errors.push(e); Vec::new() });
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(Some(Workspace { members, exclude }))
         }
     }
 
-    fn complex(name: &'a str, target: Option<&'a str>, table: TableEntry<'a>) -> Dependency<'a> {
-        fn get_string<'b>(tabl: TableEntry<'b>, key: &'b str) -> Option<&'b str> {
-            match tabl.get(key) {
-                Some(EntryRef::String(s)) => Some(s.get()),
-                _ => None
+    // Just `workspace.members`, without the rest of get_workspace
+    pub fn get_workspace_members(&self) -> Result<Vec<&str>, Vec<PathError>> {
+        self.workspace_paths("members")
+    }
+
+    pub fn get_workspace_exclude(&self) -> Result<Vec<&str>, Vec<PathError>> {
+        self.workspace_paths("exclude")
+    }
+
+    fn workspace_paths(&self, key: &'static str) -> Result<Vec<&str>, Vec<PathError>> {
+        match self.doc.get("workspace") {
+            Some(EntryRef::Table(table)) => {
+                get_string_array_entry(table.get(key), format!("workspace.{}", key))
+                    .map_err(|e| vec![e])
+            }
+            Some(entry) => {
+                let error = PathError {
+                    path: "workspace".to_owned(),
+                    expected: "table",
+                    got: entry_kind(entry),
+                    severity: Severity::Error
+                };
+                Err(vec![error])
             }
+            None => Ok(Vec::new())
         }
-        Dependency {
-            name: name,
-            version: get_string(table, "version"),
-            git: get_string(table, "git"),
-            path: get_string(table, "path"),
-            target: target,
+    }
+
+    // `package.workspace` only says where the root is, a member without it
+    // is found by Cargo searching parent directories, which we can't see from here
+    pub fn workspace_role(&self) -> WorkspaceRole {
+        let has_package = self.doc.get("package").is_some();
+        if self.doc.get("workspace").is_some() {
+            if has_package {
+                WorkspaceRole::RootAndMember
+            } else {
+                WorkspaceRole::Root
+            }
+        } else if has_package && Manifest::lookup(&self.doc, &["package", "workspace"]).is_ok() {
+            WorkspaceRole::Member
+        } else {
+            WorkspaceRole::Standalone
+        }
+    }
+
+    pub fn workspace_path(&self) -> Result<Option<&str>, QueryError> {
+        match Manifest::lookup(&self.doc, &["package", "workspace"]) {
+            Ok(EntryRef::String(value)) => Ok(Some(value.get())),
+            Ok(entry) => Err(QueryError::conflict(&["package", "workspace"], entry_kind(entry))),
+            Err(QueryError::Vacant { .. }) => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+
+    // Reported once per dependency and feature, no matter how often it repeats
+    pub fn duplicate_dependency_features(&self) -> Vec<(String, String)> {
+        let mut duplicates = Vec::new();
+        for (_, table) in self.dependency_tables() {
+            for (name, entry) in table.iter() {
+                let features = match entry {
+                    EntryRef::Table(dep) => get_string_array_entry(dep.get("features"), String::new()),
+                    _ => continue
+                };
+                let features = match features {
+                    Ok(features) => features,
+                    Err(..) => continue
+                };
+                for (idx, feature) in features.iter().enumerate() {
+                    let duplicate = (name.to_owned(), feature.to_string());
+                    if features[..idx].contains(feature) && !duplicates.contains(&duplicate) {
+                        duplicates.push(duplicate);
+                    }
+                }
+            }
+        }
+        duplicates
+    }
+
+    // Names of the `optional = true` dependencies, each listed once even if
+    // it's optional in several sections
+    pub fn optional_dependencies(&self) -> Result<Vec<&str>, Vec<PathError>> {
+        let mut names = Vec::new();
+        let mut errors = Vec::new();
+        for (path, table) in self.dependency_tables() {
+            for (name, entry) in table.iter() {
+                let dep = match entry {
+                    EntryRef::Table(dep) => dep,
+                    _ => continue
+                };
+                match dep.get("optional") {
+                    Some(EntryRef::Boolean(value)) if value.get() && !names.contains(&name) => {
+                        names.push(name);
+                    }
+                    Some(EntryRef::Boolean(..)) => {}
+                    Some(entry) => {
+                        let error = PathError {
+                            path: format!("{}.{}.optional", path, name),
+                            expected: "boolean",
+                            got: entry_kind(entry),
+                            severity: Severity::Error
+                        };
+                        errors.push(error);
+                    }
+                    None => {}
+                }
+            }
+        }
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(names)
+        }
+    }
+
+    // Gives every optional dependency that isn't yet enabled through `dep:`
+    // an explicit `name = ["dep:name"]` feature, so nothing depends on the
+    // implicit feature anymore. Features that already have the dependency's
+    // name are left alone.
+    pub fn migrate_to_dep_syntax(&mut self) -> Result<usize, Vec<PathError>> {
+        fn add_inline(table: &mut InlineTable, names: &[String]) {
+            for name in names {
+                let idx = table.len();
+                table.insert_array(idx, name.clone()).insert_string(0, format!("dep:{}", name));
+            }
+        }
+        fn add_container(cnt: &mut Container, names: &[String]) {
+            for name in names {
+                let idx = cnt.len_children();
+                cnt.insert_array(idx, name.clone()).insert_string(0, format!("dep:{}", name));
+            }
+        }
+        let missing = {
+            let optional = self.optional_dependencies()?;
+            let features = self.get_features()?;
+            optional.into_iter()
+                    .filter(|name| {
+                        let dep = format!("dep:{}", name);
+                        !features.iter().any(|&(feature, ref enables)| {
+                            feature == *name || enables.contains(&&*dep)
+                        })
+                    })
+                    .map(|name| name.to_owned())
+                    .collect::<Vec<_>>()
+        };
+        if missing.is_empty() {
+            return Ok(0);
         }
+        match self.doc.get_mut("features") {
+            Some(EntryRefMut::Table(table)) => {
+                match table.to_value() {
+                    TableValueMut::Inline(inline) => add_inline(inline, &missing),
+                    TableValueMut::Explicit(cnt) => add_container(cnt, &missing),
+                    // Only `[features.foo]` headers make it implicit, get_features rejects those
+                    TableValueMut::Implicit => unreachable!()
+                }
+            }
+            Some(..) => unreachable!(),
+            None => {
+                let index = self.doc.len_children() + self.doc.len_containers();
+                let cnt = self.doc.insert_container(index, iter::once("features"), ContainerKind::Table);
+                add_container(cnt, &missing);
+            }
+        }
+        Ok(missing.len())
+    }
+
+    // Hashes keys and values only, so reformatting or editing comments
+    // doesn't change the result. Table keys are visited sorted. The hash is
+    // FNV-1a over a fixed little-endian encoding, so it's the same across
+    // platforms, builds and Rust versions and can be stored.
+    pub fn semantic_hash(&self) -> u64 {
+        struct Fnv(u64);
+        impl Fnv {
+            fn bytes(&mut self, bytes: &[u8]) {
+                for &b in bytes {
+                    self.0 = (self.0 ^ b as u64).wrapping_mul(0x100000001b3);
+                }
+            }
+            fn u64(&mut self, mut n: u64) {
+                for _ in 0..8 {
+                    self.bytes(&[n as u8]);
+                    n >>= 8;
+                }
+            }
+            // Length first, so `"ab", "c"` and `"a", "bc"` differ
+            fn str(&mut self, s: &str) {
+                self.u64(s.len() as u64);
+                self.bytes(s.as_bytes());
+            }
+        }
+        fn hash_table<'a, I>(entries: I, hasher: &mut Fnv)
+                             where I: Iterator<Item=(&'a str, EntryRef<'a>)> {
+            let mut entries = entries.collect::<Vec<_>>();
+            entries.sort_by_key(|&(a, _)| a);
+            hasher.u64(entries.len() as u64);
+            for (key, entry) in entries {
+                hasher.str(key);
+                hash_entry(entry, hasher);
+            }
+        }
+        fn hash_entry(entry: EntryRef, hasher: &mut Fnv) {
+            hasher.str(entry_kind(entry));
+            match entry {
+                EntryRef::String(value) => hasher.str(value.get()),
+                EntryRef::Integer(value) => hasher.u64(value.get() as u64),
+                EntryRef::Float(value) => hasher.u64(value.get().to_bits()),
+                EntryRef::Boolean(value) => hasher.u64(value.get() as u64),
+                EntryRef::Datetime(value) => hasher.str(value.get()),
+                EntryRef::Array(array) => {
+                    hasher.u64(array.len() as u64);
+                    for entry in array.iter() {
+                        hash_entry(entry, hasher);
+                    }
+                }
+                EntryRef::Table(table) => hash_table(table.iter(), hasher)
+            }
+        }
+        let mut hasher = Fnv(0xcbf29ce484222325);
+        hash_table(self.doc.iter(), &mut hasher);
+        hasher.0
+    }
+
+    // Byte offset right after the last entry of `[dependencies]` (or of
+    // `[target.x.dependencies]`), which is where a new dependency belongs.
+    // End of the document if the table would have to be created, which is also
+    // where table_container_mut puts the header of an implicit table (one only
+    // made up of `[dependencies.foo]` headers). None if there's nothing to append
+    // to, eg. it's an inline table.
+    pub fn dependency_insertion_point(&self, target: Option<&str>) -> Option<usize> {
+        let path = match target {
+            Some(target) => vec!["target", target, "dependencies"],
+            None => vec!["dependencies"]
+        };
+        let position = self.doc.iter_containers().position(|c| {
+            let keys = c.keys().markup();
+            c.kind() == ContainerKind::Table && keys.len() == path.len() &&
+            keys.iter().zip(&path).all(|(k, p)| k.get() == *p)
+        });
+        if let Some(idx) = position {
+            return Some(self.container_spans()[idx].1);
+        }
+        for depth in 1..path.len() + 1 {
+            match Manifest::lookup(&self.doc, &path[..depth]) {
+                Ok(EntryRef::Table(table)) => match table.to_value() {
+                    TableValue::Inline(..) => return None,
+                    TableValue::Explicit(..) if depth == path.len() => return None,
+                    _ => {}
+                },
+                Ok(..) => return None,
+                Err(..) => break
+            }
+        }
+        Some(self.doc.to_string().len())
+    }
+
+    // One entry per `[table]`/`[[array]]` header with the keys as written.
+    // A section runs from its opening bracket up to the next header's opening
+    // bracket, so comments right above a header belong to the section before it.
+    pub fn section_ranges(&self) -> Vec<(String, usize, usize)> {
+        let spans = self.container_spans();
+        let mut ranges = Vec::with_capacity(spans.len());
+        for (idx, container) in self.doc.iter_containers().enumerate() {
+            let name = container.keys()
+                                .markup()
+                                .iter()
+                                .map(|key| key.raw())
+                                .collect::<Vec<_>>()
+                                .join(".");
+            let end = spans.get(idx + 1).map_or(self.doc.to_string().len(), |&(start, _)| start);
+            ranges.push((name, spans[idx].0, end));
+        }
+        ranges
+    }
+
+    // Root keys Cargo would warn about as unused, in document order
+    pub fn unknown_top_level_keys(&self) -> Vec<&str> {
+        const KNOWN: &[&str] = &["package", "dependencies", "dev-dependencies",
+                                                 "build-dependencies", "target", "features",
+                                                 "lib", "bin", "bench", "test", "example",
+                                                 "profile", "workspace", "patch", "replace",
+                                                 "badges", "lints"];
+        let mut unknown = Vec::new();
+        let keys = self.doc.iter_children()
+                       .map(|child| child.key().get())
+                       .chain(self.doc.iter_containers().map(|c| c.keys().markup()[0].get()));
+        for key in keys {
+            if !KNOWN.contains(&key) && !unknown.contains(&key) {
+                unknown.push(key);
+            }
+        }
+        unknown
+    }
+
+    // Advisory only, lines are 1-based. Display reproduces the source, trivia
+    // included, so we can look at the rendered text line by line.
+    pub fn whitespace_lints(&self) -> Vec<(usize, &'static str)> {
+        // Value token of a `key = value` line: what's between `=` and the
+        // comment, if any, without the whitespace around it
+        fn value_text(line: &str) -> Option<&str> {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with('[') || trimmed.starts_with('#') {
+                return None;
+            }
+            let start = match line.find('=') {
+                Some(idx) => idx + 1,
+                None => return None
+            };
+            let mut quote = None;
+            for (idx, c) in line[start..].char_indices() {
+                match (quote, c) {
+                    (None, '#') => return Some(line[start..start + idx].trim()),
+                    (None, '"') | (None, '\'') => quote = Some(c),
+                    (Some(q), c) if q == c => quote = None,
+                    _ => {}
+                }
+            }
+            Some(line[start..].trim())
+        }
+        let text = self.doc.to_string();
+        let mut lints = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.ends_with(' ') || line.ends_with('\t') {
+                lints.push((idx + 1, "trailing whitespace"));
+            }
+            if value_text(line).is_some_and(|value| value.contains('\t')) {
+                lints.push((idx + 1, "tab in value"));
+            }
+        }
+        lints
+    }
+
+    // 1-based line of the key or `[header]` declaring `path`, keys inside
+    // inline tables included. An implicit table reports its first sub-table
+    // header.
+    pub fn key_line(&self, path: &[&str]) -> Option<usize> {
+        self.entry_span(path)
+            .map(|(start, _)| self.doc.to_string()[..start].matches('\n').count() + 1)
+    }
+
+    // Every entry of the three dependency sections, target-specific ones
+    // included, with the byte range of its source as in entry_span
+    pub fn dependencies_with_spans(&self) -> Result<Vec<SpannedDependency>, Vec<PathError>> {
+        let mut result = Vec::new();
+        let mut errors = Vec::new();
+        for &section in &[DependencySection::Normal,
+                          DependencySection::Dev,
+                          DependencySection::Build] {
+            let deps = match self.dependencies_in(section) {
+                Ok(deps) => deps,
+                Err(mut errs) => {
+                    errors.append(&mut errs);
+                    continue;
+                }
+            };
+            for dep in deps {
+                let path = match dep.target() {
+                    Some(target) => vec!["target", target, section.key(), dep.name()],
+                    None => vec![section.key(), dep.name()]
+                };
+                match self.entry_span(&path) {
+                    Some(span) => result.push((dep, span)),
+                    None => errors.push(PathError {
+                        path: path.join("."),
+                        expected: "dependency in the source",
+                        got: "none",
+                        severity: Severity::Error
+                    })
+                }
+            }
+        }
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(result)
+        }
+    }
+
+    // Byte range of the `key = value` or `[header]` and entries declaring
+    // `path`, without surrounding trivia. Same fallback as key_line for
+    // implicit tables.
+    fn entry_span(&self, path: &[&str]) -> Option<(usize, usize)> {
+        // Span of a `key = value` child rendered at `offset`, or of the entry
+        // `rest` names inside it if the value is an inline table
+        fn child_span(child: &DirectChild, offset: usize, rest: &[&str]) -> (usize, usize) {
+            if let (Some(key), ValueRef::Table(table)) = (rest.first(), child.value()) {
+                // `key =`, then the table's own leading trivia and `{`
+                let mut inner = offset + child.key().to_string().len() + 1
+                                + table.markup().get_leading_trivia().len() + 1;
+                for item in table.iter() {
+                    if item.key().get() == *key {
+                        return child_span(item, inner, &rest[1..]);
+                    }
+                    // Items are separated by a bare comma
+                    inner += item.to_string().len() + 1;
+                }
+            }
+            let end = offset + child.to_string().len() - trailing_trivia(child.value()).len();
+            (offset + child.key().get_leading_trivia().len(), end)
+        }
+        if path.is_empty() || Manifest::lookup(&self.doc, path).is_err() {
+            return None;
+        }
+        // Display reproduces the source exactly, so rendered lengths are offsets
+        let mut offset = 0;
+        for child in self.doc.iter_children() {
+            if child.key().get() == path[0] {
+                return Some(child_span(child, offset, &path[1..]));
+            }
+            offset += child.to_string().len();
+        }
+        let mut implicit = None;
+        for cnt in self.doc.iter_containers() {
+            let text = cnt.to_string();
+            let keys = cnt.keys().markup();
+            let header = offset + cnt.keys().get_leading_trivia().len();
+            let end = match cnt.iter_children().last() {
+                Some(last) => offset + text.len() - trailing_trivia(last.value()).len(),
+                None => offset + text.len() - cnt.keys().get_trailing_trivia().len()
+            };
+            if keys.len() > path.len() {
+                if implicit.is_none() && keys.iter().zip(path).all(|(k, p)| k.get() == *p) {
+                    implicit = Some((header, end));
+                }
+            } else if keys.iter().zip(path).all(|(k, p)| k.get() == *p) {
+                if keys.len() == path.len() {
+                    return Some((header, end));
+                }
+                let children = cnt.iter_children().map(|c| c.to_string()).collect::<Vec<_>>();
+                let mut child_offset = offset + text.len()
+                                       - children.iter().map(|c| c.len()).sum::<usize>();
+                for (child, child_text) in cnt.iter_children().zip(&children) {
+                    if child.key().get() == path[keys.len()] {
+                        return Some(child_span(child, child_offset, &path[keys.len() + 1..]));
+                    }
+                    child_offset += child_text.len();
+                }
+            }
+            offset += text.len();
+        }
+        implicit
+    }
+
+    // Appends a `[[bin]]` with just the keys given. False if there already is
+    // a bin called `name`, or if `bin` isn't an array of tables.
+    pub fn add_bin_full(&mut self,
+                        name: &str,
+                        path: Option<&str>,
+                        required_features: &[&str],
+                        edition: Option<&str>)
+                        -> bool {
+        match self.doc.get("bin") {
+            Some(EntryRef::Array(array)) => match array.to_value() {
+                ArrayValue::OfTables => {
+                    let taken = self.doc.iter_containers().any(|c| {
+                        Manifest::is_array_member(c, "bin") &&
+                        c.iter_children().any(|child| {
+                            child.key().get() == "name" && match child.value() {
+                                ValueRef::String(value) => value.get() == name,
+                                _ => false
+                            }
+                        })
+                    });
+                    if taken {
+                        return false;
+                    }
+                }
+                ArrayValue::Inline(..) => return false
+            },
+            Some(..) => return false,
+            None => {}
+        }
+        let index = self.doc.len_children() + self.doc.len_containers();
+        let container = self.doc.insert_container(index,
+                                                  iter::once("bin"),
+                                                  ContainerKind::ArrayMember);
+        container.insert_string(0, "name", name);
+        if let Some(path) = path {
+            let index = container.len_children();
+            container.insert_string(index, "path", path);
+        }
+        if !required_features.is_empty() {
+            let index = container.len_children();
+            let array = container.insert_array(index, "required-features");
+            for (idx, feature) in required_features.iter().enumerate() {
+                array.insert_string(idx, *feature);
+            }
+        }
+        if let Some(edition) = edition {
+            let index = container.len_children();
+            container.insert_string(index, "edition", edition);
+        }
+        true
+    }
+
+    // Moves the `from`-th `[[kind]]` section so it becomes the `to`-th one,
+    // the other sections keep their order. toml_document can't move containers,
+    // so we shuffle the rendered sections (comments above a header go with it)
+    // and parse the result again, which also invalidates target handles.
+    pub fn move_target(&mut self, kind: &str, from: usize, to: usize) -> bool {
+        let mut sections = self.doc.iter_containers().map(|c| c.to_string()).collect::<Vec<_>>();
+        let slots = self.doc
+                        .iter_containers()
+                        .enumerate()
+                        .filter(|&(_, c)| Manifest::is_array_member(c, kind))
+                        .map(|(idx, _)| idx)
+                        .collect::<Vec<_>>();
+        if from >= slots.len() || to >= slots.len() {
+            return false;
+        }
+        let moved = sections.remove(slots[from]);
+        // Moving down, the removal shifts slots[to] one up, so inserting at
+        // slots[to] lands right after it; moving up it lands right before it
+        sections.insert(slots[to], moved);
+        // Only the last section may lack the final newline
+        let last = sections.len() - 1;
+        for section in sections[..last].iter_mut() {
+            if !section.ends_with('\n') {
+                section.push('\n');
+            }
+        }
+        let mut text = self.doc.iter_children().map(|c| c.to_string()).collect::<String>();
+        text.extend(sections);
+        text.push_str(self.doc.get_trailing_trivia());
+        match Document::parse(&text) {
+            Ok(doc) => {
+                self.replace_doc(doc);
+                true
+            }
+            Err(..) => false
+        }
+    }
+
+    // Every `target.<key>` once, as written (quotes included) and the form to
+    // look it up by: quotes dropped and surrounding whitespace trimmed.
+    // Unlike `redundant_target_sections` the cfg() text stays as it is.
+    pub fn normalized_target_keys(&self) -> Vec<(String, String)> {
+        let mut keys: Vec<(String, String)> = Vec::new();
+        {
+            let mut push = |raw: &str, escaped: &str| {
+                if !keys.iter().any(|(known, _)| known == raw) {
+                    keys.push((raw.to_owned(), escaped.trim().to_owned()));
+                }
+            };
+            for container in self.doc.iter_containers() {
+                let markup = container.keys().markup();
+                if markup[0].get() != "target" {
+                    continue;
+                }
+                if markup.len() > 1 {
+                    push(markup[1].raw(), markup[1].get());
+                } else {
+                    for child in container.iter_children() {
+                        push(child.key().raw(), child.key().get());
+                    }
+                }
+            }
+        }
+        keys
+    }
+
+    // Target sections whose key says the same as an earlier one, eg.
+    // `cfg(unix)` and `cfg( all(unix) )`, and triples a cfg() section holds
+    // for too, eg. `x86_64-pc-windows-msvc` next to `cfg(windows)`. In
+    // document order.
+    pub fn redundant_target_sections(&self) -> Vec<String> {
+        let keys = self.ordered_keys(&["target"]);
+        let is_cfg = |key: &str| key.trim().starts_with("cfg(");
+        let mut seen = Vec::new();
+        let mut redundant = Vec::new();
+        for &key in &keys {
+            let normalized = normalize_target_key(key);
+            let covered = !is_cfg(key) && keys.iter().any(|&other| {
+                is_cfg(other) && target_applies(other, key)
+            });
+            if seen.contains(&normalized) || covered {
+                redundant.push(key.to_owned());
+            }
+            if !seen.contains(&normalized) {
+                seen.push(normalized);
+            }
+        }
+        redundant
+    }
+
+    // Sections go in the order the Cargo book lists them: package, targets,
+    // dependencies, target, features, workspace, patch, replace, profile,
+    // badges, anything else alphabetically. `[[array]]` members keep their
+    // order. Keys are sorted within every table, comments above a key or after
+    // its value move with it. Inline tables are written as `{ a = 1, b = 2 }`.
+    pub fn sorted_string(&self) -> String {
+        const ORDER: &[&str] = &["package", "lib", "bin", "example", "test",
+                                                 "bench", "dependencies", "dev-dependencies",
+                                                 "build-dependencies", "target", "features",
+                                                 "workspace", "patch", "replace", "profile",
+                                                 "badges"];
+        fn rank(key: &str) -> usize {
+            ORDER.iter().position(|k| *k == key).unwrap_or(ORDER.len())
+        }
+        fn render(value: ValueRef) -> String {
+            match value {
+                ValueRef::Array(array) => {
+                    let markup = array.markup();
+                    let values = array.iter().map(render).collect::<Vec<_>>();
+                    format!("{}[{}{}]{}",
+                            markup.get_leading_trivia(),
+                            values.join(","),
+                            markup.get_comma_trivia(),
+                            markup.get_trailing_trivia())
+                }
+                ValueRef::Table(table) => {
+                    let markup = table.markup();
+                    let mut children = table.iter().collect::<Vec<_>>();
+                    children.sort_by(|a, b| a.key().get().cmp(b.key().get()));
+                    let entries = children.into_iter()
+                                          .map(|c| {
+                                              let value = render(c.value());
+                                              format!("{} = {}", c.key().raw(), value.trim())
+                                          })
+                                          .collect::<Vec<_>>();
+                    let body = if !entries.is_empty() {
+                        format!("{{ {} }}", entries.join(", "))
+                    } else {
+                        "{}".to_owned()
+                    };
+                    format!("{}{}{}", markup.get_leading_trivia(), body, markup.get_trailing_trivia())
+                }
+                value => value.to_string()
+            }
+        }
+        // The parser hangs the line break ending a header on the first key and
+        // comment lines above a key on the previous value, so split the trivia
+        // back into what belongs to each key before reordering
+        fn render_children<'a, I>(children: I, after_header: bool, out: &mut String)
+                                  where I: Iterator<Item=&'a DirectChild> {
+            let mut entries = Vec::new();
+            let mut carry = String::new();
+            for (idx, child) in children.enumerate() {
+                let mut lead = child.key().get_leading_trivia();
+                if idx == 0 && after_header {
+                    lead = lead.trim_start_matches('\r').trim_start_matches('\n');
+                }
+                let trail = trailing_trivia(child.value());
+                let (own, rest) = match trail.find('\n') {
+                    Some(end) => trail.split_at(end + 1),
+                    None => (trail, "")
+                };
+                let value = render(child.value());
+                let mut text = format!("{}{}{}{}={}{}",
+                                       carry,
+                                       lead,
+                                       child.key().raw(),
+                                       child.key().get_trailing_trivia(),
+                                       &value[..value.len() - trail.len()],
+                                       own);
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                entries.push((child.key().get(), text));
+                carry = rest.to_owned();
+            }
+            entries.sort_by_key(|&(a, _)| a);
+            for (_, text) in entries {
+                out.push_str(&text);
+            }
+            out.push_str(&carry);
+        }
+        let mut out = String::new();
+        render_children(self.doc.iter_children(), false, &mut out);
+        let mut containers = self.doc.iter_containers().collect::<Vec<_>>();
+        containers.sort_by(|a, b| {
+            let a = a.keys().markup().iter().map(|k| k.get()).collect::<Vec<_>>();
+            let b = b.keys().markup().iter().map(|k| k.get()).collect::<Vec<_>>();
+            (rank(a[0]), a).cmp(&(rank(b[0]), b))
+        });
+        for container in containers {
+            let keys = container.keys();
+            let names = keys.markup().iter().map(|k| k.to_string()).collect::<Vec<_>>();
+            let (open, close) = match container.kind() {
+                ContainerKind::Table => ("[", "]"),
+                ContainerKind::ArrayMember => ("[[", "]]")
+            };
+            let lead = if out.is_empty() {
+                keys.get_leading_trivia().trim_start_matches(['\r', '\n'])
+            } else {
+                keys.get_leading_trivia()
+            };
+            out.push_str(&format!("{}{}{}{}{}",
+                                  lead,
+                                  open,
+                                  names.join("."),
+                                  close,
+                                  keys.get_trailing_trivia()));
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+            render_children(container.iter_children(), true, &mut out);
+        }
+        out.push_str(self.doc.get_trailing_trivia());
+        out
+    }
+
+    // Features reachable from `seed`, `seed` itself excluded. Bare names that
+    // aren't in [features] are implicit features of optional dependencies.
+    fn feature_closure<'a>(features: &[(&'a str, Vec<&'a str>)], seed: &str) -> Vec<String> {
+        let mut closure: Vec<&str> = Vec::new();
+        let mut current = seed;
+        let mut next = 0;
+        loop {
+            if let Some((_, enables)) = features.iter().find(|&&(name, _)| name == current) {
+                for &name in enables {
+                    if !name.starts_with("dep:") && !name.contains('/') && name != seed
+                       && !closure.contains(&name) {
+                        closure.push(name);
+                    }
+                }
+            }
+            match closure.get(next) {
+                Some(&name) => current = name,
+                None => break
+            }
+            next += 1;
+        }
+        closure.into_iter().map(|name| name.to_owned()).collect()
+    }
+
+    fn lookup<'a>(doc: &'a Document,path: &'a [&'a str]) -> Result<EntryRef<'a>, QueryError> {
+        // `full` is the whole path asked for, `path` what's left of it below `entry`
+        fn lookup_inner<'a>(entry: EntryRef<'a>,
+                            full: &'a [&'a str],
+                            path: &'a [&'a str],
+                            depth: usize)
+                            -> Result<EntryRef<'a>, QueryError> {
+            let seen = full.len() - path.len();
+            if path.is_empty() {
+                Ok(entry)
+            } else {
+                match entry {
+                    EntryRef::Table(table) => {
+                        table.get(path[0])
+                             .map_or_else(|| Err(QueryError::Vacant {
+                                              depth,
+                                              path: full[..seen + 1].join(".")
+                                          }),
+                                          |e| lookup_inner(e, full, &path[1..], depth + 1))
+                    }
+                    _ => Err(QueryError::Conflict {
+                        depth,
+                        kind: entry_kind(entry),
+                        path: full[..seen].join(".")
+                    })
+                }
+            }
+        }
+        doc.get(path[0])
+           .map_or(Err(QueryError::Vacant { depth: 0, path: path[0].to_owned() }),
+                   |entry| lookup_inner(entry, path, &path[1..], 0))
+    }
+
+    fn lookup_mut<'a>(doc: &'a mut Document, path: &'a [&'a str]) -> Option<EntryRefMut<'a>> {
+        fn lookup_inner<'a>(entry: EntryRefMut<'a>,
+                            path: &'a [&'a str])
+                            -> Option<EntryRefMut<'a>> {
+            if path.is_empty() {
+                Some(entry)
+            } else {
+                match entry {
+                    EntryRefMut::Table(table) => {
+                        table.get_mut(path[0]).and_then(|e| lookup_inner(e, &path[1..]))
+                    }
+                    _ => None
+                }
+            }
+        }
+        doc.get_mut(path[0]).and_then(|entry| lookup_inner(entry, &path[1..]))
+    }
+
+    // Keys of the table at `path` in the order they appear in the document:
+    // direct children first, then sub-tables introduced by their own headers.
+    // Iterating `TableEntry` directly would give us hash order.
+    fn ordered_keys<'a>(&'a self, path: &'a [&'a str]) -> Vec<&'a str> {
+        let mut keys = Vec::new();
+        match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Table(table)) => {
+                keys.extend(table.iter_children().map(|child| child.key().get()));
+            }
+            _ => return keys
+        }
+        for container in self.doc.iter_containers() {
+            let markup = container.keys().markup();
+            if markup.len() > path.len() && markup.iter().zip(path).all(|(m, k)| m.get() == *k) {
+                let key = markup[path.len()].get();
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys
+    }
+
+    // Every [dependencies]-like table with its path, including the target-specific ones.
+    // Entries of the wrong type are skipped, get_dependencies reports them.
+    fn dependency_tables(&self) -> Vec<(String, TableEntry<'_>)> {
+        const SECTIONS: &[&str] = &["dependencies",
+                                                    "dev-dependencies",
+                                                    "build-dependencies"];
+        let mut tables = Vec::new();
+        for section in SECTIONS {
+            if let Some(EntryRef::Table(table)) = self.doc.get(section) {
+                tables.push((section.to_string(), table));
+            }
+        }
+        if let Some(EntryRef::Table(targets)) = self.doc.get("target") {
+            for (target, target_entry) in targets.iter() {
+                if let EntryRef::Table(target_table) = target_entry {
+                    for section in SECTIONS {
+                        if let Some(EntryRef::Table(table)) = target_table.get(section) {
+                            tables.push((format!("target.{}.{}", target, section), table));
+                        }
+                    }
+                }
+            }
+        }
+        tables
+    }
+
+    // Byte range of every container's text, starting at the opening bracket
+    // of its header (leading trivia excluded) and ending after its last entry.
+    // Display reproduces the source exactly, so rendered lengths are offsets.
+    fn container_spans(&self) -> Vec<(usize, usize)> {
+        let mut offset = self.doc.iter_children().map(|child| child.to_string().len()).sum();
+        let mut spans = Vec::new();
+        for container in self.doc.iter_containers() {
+            let start = offset + container.keys().get_leading_trivia().len();
+            offset += container.to_string().len();
+            spans.push((start, offset));
+        }
+        spans
+    }
+
+    // Container of the `[a.b.c]` table at `path`, appended to the document if
+    // there's no such header yet. None if some part of `path` is something
+    // else, eg. an inline table or an array of tables.
+    fn table_container_mut(&mut self, path: &[&str]) -> Option<&mut Container> {
+        let position = self.doc.iter_containers().position(|c| {
+            let keys = c.keys().markup();
+            c.kind() == ContainerKind::Table && keys.len() == path.len() &&
+            keys.iter().zip(path).all(|(k, p)| k.get() == *p)
+        });
+        if let Some(idx) = position {
+            return Some(self.doc.get_container_mut(idx));
+        }
+        for depth in 1..path.len() + 1 {
+            match Manifest::lookup(&self.doc, &path[..depth]) {
+                Ok(EntryRef::Table(table)) => match table.to_value() {
+                    TableValue::Inline(..) => return None,
+                    TableValue::Explicit(..) if depth == path.len() => return None,
+                    _ => {}
+                },
+                Ok(..) => return None,
+                Err(..) => break
+            }
+        }
+        let index = self.doc.len_children() + self.doc.len_containers();
+        Some(self.doc.insert_container(index, path.iter().cloned(), ContainerKind::Table))
+    }
+
+    // Rewrites `old` under the `[table]` header to `new` in the source and
+    // reparses, toml_document can't rename a key without losing its trivia.
+    // False if there's no such header or no such key right under it.
+    fn rename_key(&mut self, table: &str, old: &str, new: &str) -> bool {
+        let span = {
+            let mut offset = self.doc.iter_children().map(|c| c.to_string().len()).sum::<usize>();
+            let mut span = None;
+            for cnt in self.doc.iter_containers() {
+                let text = cnt.to_string();
+                let keys = cnt.keys().markup();
+                if cnt.kind() != ContainerKind::Table || keys.len() != 1 || keys[0].get() != table {
+                    offset += text.len();
+                    continue;
+                }
+                // Header and its trivia come first, then the children as they render
+                let children = cnt.iter_children().map(|c| c.to_string()).collect::<Vec<_>>();
+                offset += text.len() - children.iter().map(|c| c.len()).sum::<usize>();
+                for (child, child_text) in cnt.iter_children().zip(&children) {
+                    if child.key().get() == old {
+                        let start = offset + child.key().get_leading_trivia().len();
+                        span = Some((start, start + child.key().raw().len()));
+                        break;
+                    }
+                    offset += child_text.len();
+                }
+                break;
+            }
+            span
+        };
+        let (start, end) = match span {
+            Some(span) => span,
+            None => return false
+        };
+        let text = self.doc.to_string();
+        let text = format!("{}{}{}", &text[..start], toml_key(new), &text[end..]);
+        match Document::parse(&text) {
+            Ok(doc) => {
+                self.reparse_keeping_handles(doc);
+                true
+            }
+            Err(..) => false
+        }
+    }
+
+    // Table that holds the values at `path`, creating `[a.b]` headers for the
+    // missing part. None if `path` runs into something that isn't a table, or
+    // into an inline table we'd have to add tables to.
+    fn parent_table_mut<'a>(&'a mut self, path: &'a [&'a str]) -> Option<ParentTable<'a>> {
+        if path.is_empty() {
+            return Some(ParentTable::Document(&mut self.doc));
+        }
+        let explicit = match Manifest::lookup(&self.doc, path) {
+            Ok(EntryRef::Table(table)) => match table.to_value() {
+                TableValue::Inline(..) | TableValue::Explicit(..) => true,
+                TableValue::Implicit => false
+            },
+            Ok(..) => return None,
+            Err(..) => false
+        };
+        if !explicit {
+            return self.table_container_mut(path).map(ParentTable::Container);
+        }
+        match Manifest::lookup_mut(&mut self.doc, path) {
+            Some(EntryRefMut::Table(table)) => match table.to_value() {
+                TableValueMut::Inline(inline) => Some(ParentTable::Inline(inline)),
+                TableValueMut::Explicit(cnt) => Some(ParentTable::Container(cnt)),
+                TableValueMut::Implicit => unreachable!()
+            },
+            _ => unreachable!()
+        }
+    }
+
+    // The newline after a `[header]` is part of the first key's leading trivia,
+    // so replacing or removing that key glues the next one to the header, and
+    // removing the last one leaves the header without a line break.
+    // toml_document puts a break after the header when a key goes into an
+    // empty table, so an emptied one gets it back in place by adding a key and
+    // taking it out again. Key trivia can't be set, so a glued key is fixed in
+    // the text and reparsed, with handles carried over.
+    fn restore_header_newlines(&mut self) {
+        let count = self.doc.iter_containers().count();
+        for idx in 0..count {
+            let bare = {
+                let cnt = self.doc.get_container(idx);
+                // The line break may have ended up in the next header's trivia
+                let next = if idx + 1 < count {
+                    self.doc.get_container(idx + 1).keys().get_leading_trivia()
+                } else {
+                    self.doc.get_trailing_trivia()
+                };
+                cnt.len_children() == 0 && !cnt.to_string().ends_with('\n')
+                && !next.starts_with('\n')
+            };
+            if bare {
+                let cnt = self.doc.get_container_mut(idx);
+                cnt.insert_boolean(0, "placeholder", true);
+                cnt.remove(0);
+            }
+        }
+        let mut text = self.doc.to_string();
+        let mut offset = self.doc.iter_children().map(|c| c.to_string().len()).sum::<usize>();
+        let mut breaks = Vec::new();
+        for cnt in self.doc.iter_containers() {
+            offset += cnt.to_string().len();
+            if cnt.len_children() > 0 && !cnt.keys().get_trailing_trivia().contains('\n')
+               && !cnt.get_child(0).key().get_leading_trivia().contains('\n') {
+                let children = cnt.iter_children().map(|c| c.to_string().len()).sum::<usize>();
+                breaks.push(offset - children);
+            }
+        }
+        if breaks.is_empty() {
+            return;
+        }
+        for idx in breaks.into_iter().rev() {
+            text.insert(idx, '\n');
+        }
+        if let Ok(doc) = Document::parse(&text) {
+            self.reparse_keeping_handles(doc);
+        }
+    }
+
+    // Swaps in `doc`, handles taken so far become unknown
+    fn replace_doc(&mut self, doc: Document) {
+        let old = mem::replace(&mut self.doc, doc);
+        self.retired.push(old);
+    }
+
+    // replace_doc for a `doc` parsed from this one's text with only trivia,
+    // keys or scalars changed. Its target nodes line up with ours one to one,
+    // so the handles taken so far keep naming the same targets.
+    fn reparse_keeping_handles(&mut self, doc: Document) {
+        let old = Manifest::target_nodes(&self.doc);
+        let new = Manifest::target_nodes(&doc);
+        for &mut (_, ref mut node) in self.moved.iter_mut() {
+            if let Some(idx) = old.iter().position(|old| old == node) {
+                *node = new[idx];
+            }
+        }
+        self.moved.extend(old.into_iter().zip(new));
+        self.replace_doc(doc);
+    }
+
+    // Nodes an output target handle can point at, in layout order
+    fn target_nodes(doc: &Document) -> Vec<usize> {
+        let mut nodes = Vec::new();
+        for child in doc.iter_children() {
+            match child.value() {
+                ValueRef::Table(table) => nodes.push(table.ptr()),
+                ValueRef::Array(array) => {
+                    for value in array.iter() {
+                        if let ValueRef::Table(table) = value {
+                            nodes.push(table.ptr());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        nodes.extend(doc.iter_containers().map(|cnt| cnt.ptr()));
+        nodes
+    }
+
+    // Node `handle` names in the current document
+    fn current_node(&self, handle: usize) -> usize {
+        match self.moved.iter().find(|&&(moved, _)| moved == handle) {
+            Some(&(_, node)) => node,
+            None => handle
+        }
+    }
+
+    fn empty_table_indices(&self) -> Vec<usize> {
+        let containers = self.doc.iter_containers().collect::<Vec<_>>();
+        let has_subtables = |keys: &[TableKeyMarkup]| containers.iter().any(|other| {
+            let other = other.keys().markup();
+            other.len() > keys.len() && other.iter().zip(keys).all(|(o, k)| o.get() == k.get())
+        });
+        containers.iter()
+                  .enumerate()
+                  .filter(|&(_, cnt)| {
+                      let keys = cnt.keys().markup();
+                      cnt.kind() == ContainerKind::Table && cnt.len_children() == 0
+                      && !(keys.len() == 1 && keys[0].get() == "workspace")
+                      && !has_subtables(keys)
+                  })
+                  .map(|(idx, _)| idx)
+                  .collect()
+    }
+
+    fn is_array_member(container: &Container, kind: &str) -> bool {
+        let keys = container.keys().markup();
+        container.kind() == ContainerKind::ArrayMember && keys.len() == 1 && keys[0].get() == kind
+    }
+
+    fn add_output_target(&mut self, target: OutputTarget) -> usize {
+        let kind = if target.kind == "lib" {
+            ContainerKind::Table
+        } else {
+            ContainerKind::ArrayMember
+        };
+        let index = self.doc.len();
+        let container = self.doc.insert_container(index, iter::once(target.kind), kind);
+        Manifest::add_output_target_inner(container, target);
+        container.ptr()
+    }
+
+    fn add_output_target_inner(container: &mut Container, target: OutputTarget) {
+        fn append_bool(cnt: &mut Container, key: &'static str, value: Option<bool>) {
+            if let Some(value) = value {
+                let index = cnt.len_children();
+                cnt.insert_boolean(index, key, value);
+            }
+        }
+        fn append_string(cnt: &mut Container, key: &'static str, value: Option<&str>) {
+            if let Some(value) = value {
+                let index = cnt.len_children();
+                cnt.insert_string(index, key, value);
+            }
+        }
+        set_output_target!(container, target, append_string, append_bool);
+    }
+
+    // Some(handle) if a new `[kind]` table was created for handle 0
+    fn set_output_target(&mut self, target: OutputTarget) -> Result<Option<usize>, UnknownHandle> {
+        if target.handle == 0 {
+            let length = self.doc.len();
+            let container = self.doc.insert_container(length,
+                                                      iter::once(target.kind),
+                                                      ContainerKind::Table);
+            Manifest::set_output_target_inner(container, target);
+            return Ok(Some(container.ptr()));
+        }
+        let unknown = UnknownHandle(target.handle);
+        let node = self.current_node(target.handle);
+        let maybe_index = self.doc.find(&NodeCursor(node));
+        match maybe_index {
+            Some(index) if index < self.doc.len_children() => {
+                match self.doc.get_child_mut(index).value_mut() {
+                    ValueRefMut::Table(table) => {
+                        Manifest::set_output_target_inline_table(table, target)
+                    }
+                    _ => return Err(unknown)
+                }
+            }
+            Some(index) => {
+                let children_len = self.doc.len_children();
+                let container = self.doc.get_container_mut(index - children_len);
+                Manifest::set_output_target_inner(container, target);
+            }
+            None => {
+                let inline_array = match self.doc.get_mut(target.kind) {
+                    Some(EntryRefMut::Array(array)) => match array.to_value() {
+                        ArrayValueMut::Inline(inline_array) => inline_array,
+                        ArrayValueMut::OfTables => return Err(unknown)
+                    },
+                    _ => return Err(unknown)
+                };
+                if !Manifest::set_output_target_inline_array(inline_array, node, target) {
+                    return Err(unknown);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn set_output_target_inner(container: &mut Container, target: OutputTarget) {
+        fn set_bool(cnt: &mut Container, key: &'static str, value: Option<bool>) {
+            if let Some(value) = value {
+                Manifest::remove_child(cnt, key);
+                let index = cnt.len_children();
+                cnt.insert_boolean(index, key, value);
+            }
+        }
+        fn set_string(cnt: &mut Container, key: &'static str, value: Option<&str>) {
+            if let Some(value) = value {
+                Manifest::remove_child(cnt, key);
+                let index = cnt.len_children();
+                cnt.insert_string(index, key, value);
+            }
+        }
+        set_output_target!(container, target, set_string, set_bool);
+    }
+
+    fn set_output_target_inline_array(inline_array: &mut InlineArray,
+                                      node: usize,
+                                      target: OutputTarget)
+                                      -> bool {
+        let idx = match inline_array.find(&NodeCursor(node)) {
+            Some(idx) => idx,
+            None => return false
+        };
+        match inline_array.get_mut(idx) {
+            ValueRefMut::Table(table) => Manifest::set_output_target_inline_table(table, target),
+            _ => return false
+        }
+        true
+    }
+
+    fn set_output_target_inline_table(table: &mut InlineTable, target: OutputTarget) {
+        fn set_bool(table: &mut InlineTable, key: &'static str, value: Option<bool>) {
+            if let Some(value) = value {
+                Manifest::remove_child_inline(table, key);
+                let index = table.len();
+                table.insert_boolean(index, key, value);
+            }
+        }
+        fn set_string(table: &mut InlineTable, key: &'static str, value: Option<&str>) {
+            if let Some(value) = value {
+                Manifest::remove_child_inline(table, key);
+                let index = table.len();
+                table.insert_string(index, key, value);
+            }
+        }
+        set_output_target!(table, target, set_string, set_bool);
+    }
+
+    fn remove_output_target(&mut self, handle: usize, kind: &str) -> Result<(), UnknownHandle> {
+        fn remove_child(doc: &mut Document, key: &str) {
+            if let Some(idx) = doc.iter_children().position(|c| key == c.key().get()) {
+                doc.remove(idx)
+            }
+        }
+        if handle == 0 {
+            remove_child(&mut self.doc, kind);
+            Manifest::remove_containers(&mut self.doc, iter::once(kind));
+        } else {
+            match self.doc.find(&NodeCursor(self.current_node(handle))) {
+                Some(position) => self.doc.remove(position),
+                None => return Err(UnknownHandle(handle))
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_child(cnt: &mut Container, key: &str) {
+        if let Some(idx) = cnt.iter_children().position(|c| key == c.key().get()) {
+            cnt.remove(idx)
+        }
+    }
+
+    fn remove_child_inline(table: &mut InlineTable, key: &str) {
+        if let Some(idx) = table.iter().position(|c| key == c.key().get()) {
+            table.remove(idx)
+        }
+    }
+
+    fn remove_containers<'a, I:Iterator<Item=&'a str>+Clone>(doc: &mut Document, keys: I) {
+        fn find_container<'a, I>(doc: &mut Document, keys: I)
+                                -> Option<usize> where I:Iterator<Item=&'a str>+Clone {
+            doc.iter_containers().position(|c| {
+                let markup = c.keys().markup();
+                markup.len() >= keys.clone().count() &&
+                markup.iter().zip(keys.clone()).all(|(m, k)| m.get() == k)
+            })
+        }
+        loop {
+            let position = find_container(doc, keys.clone());
+            match position {
+                Some(position) => {
+                    let len_children = doc.len_children();
+                    doc.remove(position + len_children);
+                }
+                None => break
+            }
+        }
+    }
+}
+impl std::panic::RefUnwindSafe for Manifest { }
+
+enum ParentTable<'a> {
+    Document(&'a mut Document),
+    Container(&'a mut Container),
+    Inline(&'a mut InlineTable)
+}
+
+impl<'a> ParentTable<'a> {
+    // Inline tables can only be changed by replacing the value
+    fn get_string_mut(&mut self, key: &str) -> Option<&mut StringValue> {
+        let entry = match *self {
+            ParentTable::Document(ref mut doc) => doc.get_mut(key),
+            ParentTable::Container(ref mut cnt) => cnt.get_mut(key),
+            ParentTable::Inline(..) => None
+        };
+        match entry {
+            Some(EntryRefMut::String(value)) => Some(value),
+            _ => None
+        }
+    }
+
+    fn get_bool_mut(&mut self, key: &str) -> Option<&mut BoolValue> {
+        let entry = match *self {
+            ParentTable::Document(ref mut doc) => doc.get_mut(key),
+            ParentTable::Container(ref mut cnt) => cnt.get_mut(key),
+            ParentTable::Inline(..) => None
+        };
+        match entry {
+            Some(EntryRefMut::Boolean(value)) => Some(value),
+            _ => None
+        }
+    }
+
+    fn get_integer_mut(&mut self, key: &str) -> Option<&mut IntegerValue> {
+        let entry = match *self {
+            ParentTable::Document(ref mut doc) => doc.get_mut(key),
+            ParentTable::Container(ref mut cnt) => cnt.get_mut(key),
+            ParentTable::Inline(..) => None
+        };
+        match entry {
+            Some(EntryRefMut::Integer(value)) => Some(value),
+            _ => None
+        }
+    }
+
+    fn get_float_mut(&mut self, key: &str) -> Option<&mut FloatValue> {
+        let entry = match *self {
+            ParentTable::Document(ref mut doc) => doc.get_mut(key),
+            ParentTable::Container(ref mut cnt) => cnt.get_mut(key),
+            ParentTable::Inline(..) => None
+        };
+        match entry {
+            Some(EntryRefMut::Float(value)) => Some(value),
+            _ => None
+        }
+    }
+
+    fn get_array_mut(&mut self, key: &str) -> Option<&mut InlineArray> {
+        let entry = match *self {
+            ParentTable::Document(ref mut doc) => doc.get_mut(key),
+            ParentTable::Container(ref mut cnt) => cnt.get_mut(key),
+            ParentTable::Inline(..) => None
+        };
+        match entry {
+            Some(EntryRefMut::Array(array)) => match array.to_value() {
+                ArrayValueMut::Inline(array) => Some(array),
+                ArrayValueMut::OfTables => None
+            },
+            _ => None
+        }
+    }
+
+    // Removes the value at `key` and returns the index to insert its
+    // replacement at. None if `key` is a table with its own header.
+    fn take_slot(&mut self, key: &str) -> Option<usize> {
+        let (position, len) = match *self {
+            ParentTable::Document(ref doc) => {
+                if doc.get(key).is_some_and(|entry| !entry.is_child()) {
+                    return None;
+                }
+                (doc.iter_children().position(|c| c.key().get() == key), doc.len_children())
+            }
+            ParentTable::Container(ref cnt) => {
+                if cnt.get(key).is_some_and(|entry| !entry.is_child()) {
+                    return None;
+                }
+                (cnt.iter_children().position(|c| c.key().get() == key), cnt.len_children())
+            }
+            ParentTable::Inline(ref table) => {
+                (table.iter().position(|c| c.key().get() == key), table.len())
+            }
+        };
+        match position {
+            Some(idx) => {
+                match *self {
+                    ParentTable::Document(ref mut doc) => doc.remove(idx),
+                    ParentTable::Container(ref mut cnt) => cnt.remove(idx),
+                    ParentTable::Inline(ref mut table) => table.remove(idx)
+                }
+                Some(idx)
+            }
+            None => Some(len)
+        }
+    }
+
+    fn insert_string(&mut self, idx: usize, key: &str, value: &str) {
+        match *self {
+            ParentTable::Document(ref mut doc) => { doc.insert_string(idx, key, value); }
+            ParentTable::Container(ref mut cnt) => { cnt.insert_string(idx, key, value); }
+            ParentTable::Inline(ref mut table) => { table.insert_string(idx, key, value); }
+        }
+    }
+
+    fn insert_boolean(&mut self, idx: usize, key: &str, value: bool) {
+        match *self {
+            ParentTable::Document(ref mut doc) => { doc.insert_boolean(idx, key, value); }
+            ParentTable::Container(ref mut cnt) => { cnt.insert_boolean(idx, key, value); }
+            ParentTable::Inline(ref mut table) => { table.insert_boolean(idx, key, value); }
+        }
+    }
+
+    fn insert_integer(&mut self, idx: usize, key: &str, value: i64) {
+        match *self {
+            ParentTable::Document(ref mut doc) => { doc.insert_integer(idx, key, value); }
+            ParentTable::Container(ref mut cnt) => { cnt.insert_integer(idx, key, value); }
+            ParentTable::Inline(ref mut table) => { table.insert_integer(idx, key, value); }
+        }
+    }
+
+    fn insert_float(&mut self, idx: usize, key: &str, value: f64) {
+        match *self {
+            ParentTable::Document(ref mut doc) => { doc.insert_float(idx, key, value); }
+            ParentTable::Container(ref mut cnt) => { cnt.insert_float(idx, key, value); }
+            ParentTable::Inline(ref mut table) => { table.insert_float(idx, key, value); }
+        }
+    }
+
+    fn insert_array(&mut self, idx: usize, key: &str) -> &mut InlineArray {
+        match *self {
+            ParentTable::Document(ref mut doc) => doc.insert_array(idx, key),
+            ParentTable::Container(ref mut cnt) => cnt.insert_array(idx, key),
+            ParentTable::Inline(ref mut table) => table.insert_array(idx, key)
+        }
+    }
+
+    fn insert_inline_table(&mut self, idx: usize, key: &str) -> &mut InlineTable {
+        match *self {
+            ParentTable::Document(ref mut doc) => doc.insert_inline_table(idx, key),
+            ParentTable::Container(ref mut cnt) => cnt.insert_inline_table(idx, key),
+            ParentTable::Inline(ref mut table) => table.insert_inline_table(idx, key)
+        }
+    }
+}
+
+struct NodeCursor(usize);
+impl InternalNode for NodeCursor {
+    fn ptr(&self) -> usize {
+        self.0
+    }
+}
+
+// Output target handle that doesn't name a target (any more), eg. one taken
+// before remove_output_target or prune_empty_tables
+struct UnknownHandle(usize);
+
+impl Display for UnknownHandle {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "no output target with handle {}", self.0)
+    }
+}
+
+
+
+#[derive(Copy, Clone)]
+pub enum Section {
+    Dependencies,
+    Targets,
+    Package,
+    Features,
+    Profiles
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WorkspaceRole {
+    Root,
+    RootAndMember,
+    Member,
+    Standalone
+}
+
+// `path` is the dotted path of the missing or mistyped entry, as far as
+// the lookup got, eg. `package.version` when [package] has no version
+#[derive(Debug)]
+pub enum QueryError {
+    Vacant{ depth: usize, path: String },
+    Conflict{ depth: usize, kind: &'static str, path: String }
+}
+
+impl QueryError {
+    // The entry at `path` is there but has the wrong kind
+    fn conflict(path: &[&str], kind: &'static str) -> QueryError {
+        QueryError::Conflict { depth: path.len(), kind, path: path.join(".") }
+    }
+
+    pub fn path(&self) -> &str {
+        match *self {
+            QueryError::Vacant{ ref path, .. } | QueryError::Conflict{ ref path, .. } => path
+        }
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            QueryError::Vacant{ ref path, .. } => write!(f, "no value found at `{}`", path),
+            QueryError::Conflict{ kind, ref path, .. } => {
+                write!(f, "unexpected {} at `{}`", kind, path)
+            }
+        }
+    }
+}
+
+impl error::Error for QueryError {
+    fn description(&self) -> &str {
+        match *self {
+            QueryError::Vacant{ .. } => "no value found",
+            QueryError::Conflict{ .. } => "value of the wrong type"
+        }
+    }
+}
+
+// Editions set_edition accepts, oldest first
+pub const EDITIONS: &[&str] = &["2015", "2018", "2021"];
+
+// Conflict means `package` is something set_string can't write into
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EditionError {
+    Unknown { allowed: &'static [&'static str] },
+    Conflict
+}
+
+// OtherPrerelease is a pre-release that isn't `<label>.<number>`, WriteFailed
+// means the bumped version was worked out but couldn't be stored
+pub enum BumpError {
+    Query(QueryError),
+    Malformed,
+    OtherPrerelease,
+    WriteFailed
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Readme<'a> {
+    Path(&'a str),
+    Disabled,
+    Default
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum LeafValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool)
+}
+
+// Mismatch carries the version found instead, None if there's no string version
+#[derive(PartialEq, Debug)]
+pub enum CasResult {
+    Updated,
+    Mismatch(Option<String>),
+    Absent
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BuildScript<'a> {
+    Path(&'a str),
+    Disabled,
+    Default
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ArrayLayout {
+    Inline,
+    Multiline,
+    Absent
+}
+
+// A dependency and the byte range of its source, see dependencies_with_spans
+pub type SpannedDependency<'a> = (Dependency<'a>, (usize, usize));
+
+pub struct Dependency<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+    git: Option<&'a str>,
+    path: Option<&'a str>,
+    branch: Option<&'a str>,
+    tag: Option<&'a str>,
+    rev: Option<&'a str>,
+    features: Vec<&'a str>,
+    // None if absent, to_toml_string only writes what was written
+    optional: Option<bool>,
+    default_features: Option<bool>,
+    target: Option<&'a str>,
+    // Set by whoever read the table, simple/complex don't know
+    section: DependencySection,
+    features_layout: ArrayLayout
+}
+
+impl<'a> Dependency<'a> {
+    fn simple(name: &'a str, target: Option<&'a str>, version: &'a str) -> Dependency<'a> {
+        Dependency {
+            name,
+            version: Some(version),
+            git: None,
+            path: None,
+            branch: None,
+            tag: None,
+            rev: None,
+            features: Vec::new(),
+            optional: None,
+            default_features: None,
+            target,
+            section: DependencySection::Normal,
+            features_layout: ArrayLayout::Absent
+        }
+    }
+
+    fn complex(name: &'a str,
+               target: Option<&'a str>,
+               table: TableEntry<'a>,
+               path: &str,
+               errors: &mut Vec<PathError>)
+               -> Dependency<'a> {
+        fn get_string<'b>(tabl: TableEntry<'b>, key: &'b str) -> Option<&'b str> {
+            match tabl.get(key) {
+                Some(EntryRef::String(s)) => Some(s.get()),
+                _ => None
+            }
+        }
+        fn get_bool(tabl: TableEntry,
+                    key: &str,
+                    path: &str,
+                    errors: &mut Vec<PathError>)
+                    -> Option<bool> {
+            match tabl.get(key) {
+                Some(EntryRef::Boolean(b)) => Some(b.get()),
+                Some(entry) => {
+                    errors.push(PathError {
+                        path: format!("{}.{}", path, key),
+                        expected: "boolean",
+                        got: entry_kind(entry),
+                        severity: Severity::Error
+                    });
+                    None
+                }
+                None => None
+            }
+        }
+        // Multiline if there's a line break anywhere between the brackets
+        fn get_layout(tabl: TableEntry) -> ArrayLayout {
+            let array = match tabl.get("features") {
+                Some(EntryRef::Array(array)) => array,
+                _ => return ArrayLayout::Absent
+            };
+            match array.to_value() {
+                ArrayValue::Inline(array) => {
+                    let text = array.to_string();
+                    let markup = array.markup();
+                    let inner = &text[markup.get_leading_trivia().len()
+                                      ..text.len() - markup.get_trailing_trivia().len()];
+                    if inner.contains('\n') {
+                        ArrayLayout::Multiline
+                    } else {
+                        ArrayLayout::Inline
+                    }
+                }
+                ArrayValue::OfTables => ArrayLayout::Absent
+            }
+        }
+        Dependency {
+            name,
+            version: get_string(table, "version"),
+            git: get_string(table, "git"),
+            path: get_string(table, "path"),
+            branch: get_string(table, "branch"),
+            tag: get_string(table, "tag"),
+            rev: get_string(table, "rev"),
+            features: match get_string_array_entry(table.get("features"),
+                                                   format!("{}.features", path)) {
+                Ok(features) => features,
+                Err(error) => {
+                    errors.push(error);
+                    Vec::new()
+                }
+            },
+            optional: get_bool(table, "optional", path, errors),
+            // `default_features` is the deprecated spelling, lints() reports it
+            default_features: get_bool(table, "default-features", path, errors)
+                                  .or_else(|| get_bool(table, "default_features", path, errors)),
+            target,
+            section: DependencySection::Normal,
+            features_layout: get_layout(table)
+        }
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn version(&self) -> Option<&'a str> {
+        self.version
+    }
+
+    pub fn git(&self) -> Option<&'a str> {
+        self.git
+    }
+
+    pub fn path(&self) -> Option<&'a str> {
+        self.path
+    }
+
+    pub fn branch(&self) -> Option<&'a str> {
+        self.branch
+    }
+
+    pub fn tag(&self) -> Option<&'a str> {
+        self.tag
+    }
+
+    pub fn rev(&self) -> Option<&'a str> {
+        self.rev
+    }
+
+    pub fn target(&self) -> Option<&'a str> {
+        self.target
+    }
+
+    pub fn section(&self) -> DependencySection {
+        self.section
+    }
+
+    // Identifies the row across reloads, so nothing that can be edited in place
+    // (version, features, ...) goes in. The key under the section is used rather
+    // than a renamed `package`, since two renames of one package are two rows.
+    pub fn stable_key(&self) -> String {
+        let section = match self.section {
+            DependencySection::Normal => "normal",
+            DependencySection::Dev => "dev",
+            DependencySection::Build => "build"
+        };
+        format!("{}::{}::{}", section, self.target.unwrap_or("global"), self.name)
+    }
+
+    pub fn features(&self) -> &[&'a str] {
+        &self.features
+    }
+
+    pub fn features_layout(&self) -> ArrayLayout {
+        self.features_layout
+    }
+
+    // By the first operator, `>= 1, < 2` is a Comparator. Bare versions are
+    // caret requirements to Cargo, `1.*` is a Wildcard like `*`.
+    pub fn requirement_kind(&self) -> Option<RequirementKind> {
+        let version = match self.version.map(|v| v.trim()) {
+            Some(version) if !version.is_empty() => version,
+            _ => return None
+        };
+        let kind = match version.as_bytes()[0] {
+            b'^' => RequirementKind::Caret,
+            b'~' => RequirementKind::Tilde,
+            b'=' => RequirementKind::Exact,
+            b'>' | b'<' => RequirementKind::Comparator,
+            _ if version.split(&['.', ','][..]).any(|part| part.trim() == "*") => {
+                RequirementKind::Wildcard
+            }
+            _ => RequirementKind::Caret
+        };
+        Some(kind)
+    }
+
+    pub fn kind(&self) -> DependencyKind {
+        match (self.git, self.path, self.version) {
+            (Some(..), _, _) => DependencyKind::Git,
+            (None, Some(..), _) => DependencyKind::Path,
+            (None, None, Some(..)) => DependencyKind::Registry,
+            (None, None, None) => DependencyKind::Detached
+        }
+    }
+
+    // Absent means not optional
+    pub fn optional(&self) -> bool {
+        self.optional.unwrap_or(false)
+    }
+
+    // Absent `default-features` means on, as cargo treats it
+    pub fn default_features(&self) -> bool {
+        self.default_features.unwrap_or(true)
+    }
+
+    pub fn effective_features_note(&self) -> FeatureNote<'a> {
+        FeatureNote {
+            explicit: self.features.clone(),
+            default_features_enabled: self.default_features()
+        }
+    }
+
+    // Compares what the dependency resolves to, ignoring the target it's declared for.
+    // Features compare as a set, `optional`/`default-features` by their effective value
+    pub fn semantically_eq(&self, other: &Dependency) -> bool {
+        fn feature_set<'a>(dep: &Dependency<'a>) -> Vec<&'a str> {
+            let mut features = dep.features.clone();
+            features.sort();
+            features.dedup();
+            features
+        }
+        self.name == other.name
+        && self.version == other.version
+        && self.git == other.git
+        && self.path == other.path
+        && self.branch == other.branch
+        && self.tag == other.tag
+        && self.rev == other.rev
+        && feature_set(self) == feature_set(other)
+        && self.optional() == other.optional()
+        && self.default_features() == other.default_features()
+    }
+
+    // One-line form with the keys in the order `cargo add` writes them
+    pub fn to_toml_string(&self) -> String {
+        let mut keys = Vec::new();
+        if let Some(version) = self.version {
+            keys.push(format!("version = {}", toml_string(version)));
+        }
+        if !self.features.is_empty() {
+            let features = self.features.iter().map(|f| toml_string(f)).collect::<Vec<_>>();
+            keys.push(format!("features = [{}]", features.join(", ")));
+        }
+        if let Some(optional) = self.optional {
+            keys.push(format!("optional = {}", optional));
+        }
+        if let Some(default_features) = self.default_features {
+            keys.push(format!("default-features = {}", default_features));
+        }
+        let strings = [("git", self.git), ("branch", self.branch), ("tag", self.tag),
+                       ("rev", self.rev), ("path", self.path)];
+        for &(key, value) in strings.iter() {
+            if let Some(value) = value {
+                keys.push(format!("{} = {}", key, toml_string(value)));
+            }
+        }
+        let name = toml_key(self.name);
+        match (self.version, keys.len()) {
+            (Some(version), 1) => format!("{} = {}", name, toml_string(version)),
+            (_, 0) => format!("{} = {{}}", name),
+            _ => format!("{} = {{ {} }}", name, keys.join(", "))
+        }
+    }
+}
+
+pub struct FeatureNote<'a> {
+    explicit: Vec<&'a str>,
+    default_features_enabled: bool
+}
+
+impl<'a> FeatureNote<'a> {
+    pub fn explicit(&self) -> &[&'a str] {
+        &self.explicit
+    }
+
+    pub fn default_features_enabled(&self) -> bool {
+        self.default_features_enabled
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EntryKind {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Datetime,
+    Array,
+    Table
+}
+
+impl EntryKind {
+    fn of(e: EntryRef) -> EntryKind {
+        match e {
+            EntryRef::String(..) => EntryKind::String,
+            EntryRef::Integer(..) => EntryKind::Integer,
+            EntryRef::Float(..) => EntryKind::Float,
+            EntryRef::Boolean(..) => EntryKind::Boolean,
+            EntryRef::Datetime(..) => EntryKind::Datetime,
+            EntryRef::Array(..) => EntryKind::Array,
+            EntryRef::Table(..) => EntryKind::Table
+        }
+    }
+
+    // As error messages spell it
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EntryKind::String => "string",
+            EntryKind::Integer => "integer",
+            EntryKind::Float => "float",
+            EntryKind::Boolean => "boolean",
+            EntryKind::Datetime => "datetime",
+            EntryKind::Array => "array",
+            EntryKind::Table => "table"
+        }
+    }
+}
+
+// Which of `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]`
+// declares a dependency, target-specific ones included
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DependencySection {
+    Normal,
+    Dev,
+    Build
+}
+
+impl DependencySection {
+    fn key(&self) -> &'static str {
+        match *self {
+            DependencySection::Normal => "dependencies",
+            DependencySection::Dev => "dev-dependencies",
+            DependencySection::Build => "build-dependencies"
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RequirementKind {
+    Caret,
+    Tilde,
+    Exact,
+    Comparator,
+    Wildcard
+}
+
+// Git wins over path, path over version, Detached has none of them.
+// Passed through the FFI as its discriminant, keep in sync with Dependency.cs.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DependencyKind {
+    Registry = 0,
+    Git = 1,
+    Path = 2,
+    Detached = 3
+}
+
+pub struct Edge {
+    to: String,
+    requirement: Option<String>,
+    kind: DependencyKind,
+    section: DependencySection,
+    target: Option<String>
+}
+
+impl Edge {
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+
+    pub fn requirement(&self) -> Option<&str> {
+        self.requirement.as_deref()
+    }
+
+    pub fn kind(&self) -> DependencyKind {
+        self.kind
+    }
+
+    pub fn section(&self) -> DependencySection {
+        self.section
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+}
+
+pub struct ReleaseConfig {
+    sign_commit: Option<bool>,
+    sign_tag: Option<bool>,
+    push: Option<bool>,
+    publish: Option<bool>,
+    tag_name: Option<String>,
+    pre_release_commit_message: Option<String>,
+    pre_release_hook: Option<Vec<String>>,
+    allow_branch: Option<Vec<String>>
+}
+
+impl ReleaseConfig {
+    pub fn sign_commit(&self) -> Option<bool> {
+        self.sign_commit
+    }
+
+    pub fn sign_tag(&self) -> Option<bool> {
+        self.sign_tag
+    }
+
+    pub fn push(&self) -> Option<bool> {
+        self.push
+    }
+
+    pub fn publish(&self) -> Option<bool> {
+        self.publish
+    }
+
+    pub fn tag_name(&self) -> Option<&str> {
+        self.tag_name.as_deref()
+    }
+
+    pub fn pre_release_commit_message(&self) -> Option<&str> {
+        self.pre_release_commit_message.as_deref()
+    }
+
+    pub fn pre_release_hook(&self) -> Option<&[String]> {
+        self.pre_release_hook.as_deref()
+    }
+
+    pub fn allow_branch(&self) -> Option<&[String]> {
+        self.allow_branch.as_deref()
+    }
+}
+
+pub struct BadgeDisplay<'a> {
+    provider: &'a str,
+    fields: Vec<(&'a str, &'a str)>
+}
+
+impl<'a> BadgeDisplay<'a> {
+    pub fn provider(&self) -> &'a str {
+        self.provider
+    }
+
+    pub fn fields(&self) -> &[(&'a str, &'a str)] {
+        &self.fields
+    }
+}
+
+pub struct OwnedManifest {
+    package: Option<OwnedPackage>,
+    dependencies: Vec<OwnedDependency>,
+    output_targets: Vec<OwnedOutputTarget>,
+    features: Vec<(String, Vec<String>)>
+}
+
+impl OwnedManifest {
+    pub fn package(&self) -> Option<&OwnedPackage> {
+        self.package.as_ref()
+    }
+
+    pub fn dependencies(&self) -> &[OwnedDependency] {
+        &self.dependencies
+    }
+
+    pub fn output_targets(&self) -> &[OwnedOutputTarget] {
+        &self.output_targets
+    }
+
+    pub fn features(&self) -> &[(String, Vec<String>)] {
+        &self.features
+    }
+}
+
+pub struct OwnedPackage {
+    name: Option<String>,
+    version: Option<String>,
+    authors: Vec<String>,
+    description: Option<String>,
+    license: Option<String>,
+    edition: Option<String>
+}
+
+impl OwnedPackage {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub fn authors(&self) -> &[String] {
+        &self.authors
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn license(&self) -> Option<&str> {
+        self.license.as_deref()
+    }
+
+    pub fn edition(&self) -> Option<&str> {
+        self.edition.as_deref()
+    }
+}
+
+pub struct OwnedDependency {
+    name: String,
+    version: Option<String>,
+    git: Option<String>,
+    path: Option<String>,
+    branch: Option<String>,
+    tag: Option<String>,
+    rev: Option<String>,
+    features: Vec<String>,
+    optional: Option<bool>,
+    default_features: Option<bool>,
+    target: Option<String>
+}
+
+impl OwnedDependency {
+    fn from(dep: &Dependency) -> OwnedDependency {
+        OwnedDependency {
+            name: dep.name.to_owned(),
+            version: dep.version.map(|v| v.to_owned()),
+            git: dep.git.map(|g| g.to_owned()),
+            path: dep.path.map(|p| p.to_owned()),
+            branch: dep.branch.map(|b| b.to_owned()),
+            tag: dep.tag.map(|t| t.to_owned()),
+            rev: dep.rev.map(|r| r.to_owned()),
+            features: dep.features.iter().map(|f| f.to_string()).collect(),
+            optional: dep.optional,
+            default_features: dep.default_features,
+            target: dep.target.map(|t| t.to_owned())
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub fn git(&self) -> Option<&str> {
+        self.git.as_deref()
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_deref()
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    pub fn rev(&self) -> Option<&str> {
+        self.rev.as_deref()
+    }
+
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+
+    pub fn optional(&self) -> Option<bool> {
+        self.optional
+    }
+
+    pub fn default_features(&self) -> Option<bool> {
+        self.default_features
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+}
+
+// Not to be confused with capi::OwnedOutputTarget, which is the FFI mirror
+pub struct OwnedOutputTarget {
+    kind: String,
+    name: Option<String>,
+    path: Option<String>,
+    edition: Option<String>,
+    test: Option<bool>,
+    doctest: Option<bool>,
+    bench: Option<bool>,
+    doc: Option<bool>,
+    plugin: Option<bool>,
+    harness: Option<bool>,
+    required_features: Vec<String>,
+    crate_type: Vec<String>,
+    proc_macro: bool
+}
+
+impl OwnedOutputTarget {
+    fn from(t: &OutputTarget) -> OwnedOutputTarget {
+        OwnedOutputTarget {
+            kind: t.kind.to_owned(),
+            name: t.name.map(|n| n.to_owned()),
+            path: t.path.map(|p| p.to_owned()),
+            edition: t.edition.map(|e| e.to_owned()),
+            test: t.test,
+            doctest: t.doctest,
+            bench: t.bench,
+            doc: t.doc,
+            plugin: t.plugin,
+            harness: t.harness,
+            required_features: t.required_features.iter().map(|f| f.to_string()).collect(),
+            crate_type: t.crate_type.iter().map(|c| c.to_string()).collect(),
+            proc_macro: t.proc_macro
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn edition(&self) -> Option<&str> {
+        self.edition.as_deref()
+    }
+
+    pub fn test(&self) -> Option<bool> {
+        self.test
+    }
+
+    pub fn doctest(&self) -> Option<bool> {
+        self.doctest
+    }
+
+    pub fn bench(&self) -> Option<bool> {
+        self.bench
+    }
+
+    pub fn doc(&self) -> Option<bool> {
+        self.doc
+    }
+
+    pub fn plugin(&self) -> Option<bool> {
+        self.plugin
+    }
+
+    pub fn harness(&self) -> Option<bool> {
+        self.harness
+    }
+
+    pub fn required_features(&self) -> &[String] {
+        &self.required_features
+    }
+
+    pub fn crate_type(&self) -> &[String] {
+        &self.crate_type
+    }
+
+    pub fn proc_macro(&self) -> bool {
+        self.proc_macro
+    }
+}
+
+// Typed access to the well-known `[package]` keys. Missing and mistyped keys
+// both read as None here, package_errors is what reports the latter.
+pub struct PackageView<'a> {
+    manifest: &'a Manifest
+}
+
+impl<'a> PackageView<'a> {
+    pub fn name(&self) -> Option<&'a str> {
+        self.manifest.get_string(&["package", "name"]).ok()
+    }
+
+    pub fn version(&self) -> Option<&'a str> {
+        self.manifest.get_string(&["package", "version"]).ok()
+    }
+
+    // As written, Cargo's "2015" default isn't filled in
+    pub fn edition(&self) -> Option<&'a str> {
+        self.manifest.get_string(&["package", "edition"]).ok()
+    }
+
+    pub fn description(&self) -> Option<&'a str> {
+        self.manifest.get_string(&["package", "description"]).ok()
+    }
+
+    pub fn license(&self) -> Option<&'a str> {
+        self.manifest.get_string(&["package", "license"]).ok()
+    }
+
+    pub fn authors(&self) -> Vec<&'a str> {
+        self.manifest.get_string_array(&["package", "authors"]).unwrap_or_default()
+    }
+}
+
+pub struct Workspace<'a> {
+    members: Vec<&'a str>,
+    exclude: Vec<&'a str>
+}
+
+impl<'a> Workspace<'a> {
+    // We don't touch the filesystem here, `dir_entries` is the listing of
+    // directories (relative to the workspace root) supplied by the caller.
+    // A member pattern matches an entry segment by segment, `exclude` removes
+    // an entry and everything below it.
+    pub fn expand_members(&self, dir_entries: &[&str]) -> Vec<String> {
+        fn segments(path: &str) -> Vec<&str> {
+            path.split(['/', '\\'])
+                .filter(|s| !s.is_empty() && *s != ".")
+                .collect()
+        }
+        fn matches(pattern: &[&str], entry: &[&str]) -> bool {
+            pattern.len() == entry.len()
+            && pattern.iter().zip(entry).all(|(p, e)| glob_matches(p, e))
+        }
+        fn excluded(exclude: &[&str], entry: &[&str]) -> bool {
+            exclude.len() <= entry.len() && exclude.iter().zip(entry).all(|(x, e)| x == e)
+        }
+        let mut result = Vec::new();
+        for member in self.members.iter() {
+            let pattern = segments(member);
+            for entry in dir_entries {
+                let entry_segments = segments(entry);
+                if !matches(&pattern, &entry_segments) {
+                    continue;
+                }
+                if self.exclude.iter().any(|x| excluded(&segments(x), &entry_segments)) {
+                    continue;
+                }
+                let entry = entry.to_string();
+                if !result.contains(&entry) {
+                    result.push(entry);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info
+}
+
+// What the manifest itself says. When `auto_lib`/`auto_bins` is set Cargo
+// also picks up src/lib.rs, src/main.rs and src/bin/*, the caller has to
+// look at the filesystem for those.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CrateKinds {
+    has_lib: bool,
+    has_bins: bool,
+    auto_lib: bool,
+    auto_bins: bool
+}
+
+impl CrateKinds {
+    pub fn has_lib(&self) -> bool {
+        self.has_lib
+    }
+
+    pub fn has_bins(&self) -> bool {
+        self.has_bins
+    }
+
+    pub fn auto_lib(&self) -> bool {
+        self.auto_lib
+    }
+
+    pub fn auto_bins(&self) -> bool {
+        self.auto_bins
+    }
+}
+
+pub struct BuildUnit {
+    kind: String,
+    name: String,
+    effective_path: String,
+    required_features: Vec<String>,
+    edition: String
+}
+
+impl BuildUnit {
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn effective_path(&self) -> &str {
+        &self.effective_path
+    }
+
+    pub fn required_features(&self) -> &[String] {
+        &self.required_features
+    }
+
+    pub fn edition(&self) -> &str {
+        &self.edition
+    }
+}
+
+#[derive(Debug)]
+pub struct PathError {
+    path: String,
+    expected: &'static str,
+    got: &'static str,
+    severity: Severity
+}
+
+impl PathError {
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{}: expected {}, got {}", self.path, self.expected, self.got)
+    }
+}
+
+impl error::Error for PathError {
+    fn description(&self) -> &str {
+        "invalid manifest entry"
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    message: String
+}
+
+impl ParseError {
+    fn new(text: &str, err: ParserError) -> ParseError {
+        // The offset is private in toml_document, only Debug shows it:
+        // `ParserError { lo: 8, hi: 9, desc: "..." }`. The version is pinned
+        // and parse_manifest checks the format, point at the end if it changes
+        // anyway.
+        let debug = format!("{:?}", err);
+        let offset = debug.find("lo: ")
+                          .map(|idx| &debug[idx + 4..])
+                          .and_then(|rest| rest.split(',').next())
+                          .and_then(|lo| lo.parse::<usize>().ok())
+                          .map_or(text.len(), |lo| cmp::min(lo, text.len()));
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        ParseError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: err.desc
+        }
+    }
+
+    // 1-based, like the rest of the line numbers we hand out
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    // 1-based, counted in chars
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
     }
 }
 
-pub struct PathError {
-    path: String,
-    expected: &'static str,
-    got: &'static str,
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        "invalid TOML"
+    }
 }
 
 pub struct OutputTarget<'a> {
@@ -574,12 +5042,18 @@ pub struct OutputTarget<'a> {
     kind: &'a str,
     name: Option<&'a str>,
     path: Option<&'a str>,
+    // None means the package edition applies
+    edition: Option<&'a str>,
     test: Option<bool>,
     doctest: Option<bool>,
     bench: Option<bool>,
     doc: Option<bool>,
     plugin: Option<bool>,
-    harness: Option<bool>
+    harness: Option<bool>,
+    required_features: Vec<&'a str>,
+    // Only ever set for the lib
+    crate_type: Vec<&'a str>,
+    proc_macro: bool
 }
 
 impl<'a> OutputTarget<'a> {
@@ -589,12 +5063,16 @@ impl<'a> OutputTarget<'a> {
             kind: kind,
             name: None,
             path: None,
+            edition: None,
             test: None,
             doctest: None,
             bench: None,
             doc: None,
             plugin: None,
-            harness: None
+            harness: None,
+            required_features: Vec::new(),
+            crate_type: Vec::new(),
+            proc_macro: false
         }
     }
 
@@ -617,6 +5095,39 @@ impl<'a> OutputTarget<'a> {
     fn example(handle: usize) -> OutputTarget<'a> {
         OutputTarget::new(handle, "example")
     }
+
+    pub fn edition(&self) -> Option<&'a str> {
+        self.edition
+    }
+
+    pub fn required_features(&self) -> &[&'a str] {
+        &self.required_features
+    }
+
+    pub fn crate_type(&self) -> &[&'a str] {
+        &self.crate_type
+    }
+
+    pub fn proc_macro(&self) -> bool {
+        self.proc_macro
+    }
+
+    // Where Cargo looks for the target's root file when `path` isn't set
+    pub fn effective_path(&self, package_name: &str) -> String {
+        if let Some(path) = self.path {
+            return path.to_owned();
+        }
+        let name = self.name.unwrap_or(package_name);
+        match self.kind {
+            "lib" => "src/lib.rs".to_owned(),
+            "bin" if name == package_name => "src/main.rs".to_owned(),
+            "bin" => format!("src/bin/{}.rs", name),
+            "example" => format!("examples/{}.rs", name),
+            "test" => format!("tests/{}.rs", name),
+            "bench" => format!("benches/{}.rs", name),
+            _ => unreachable!()
+        }
+    }
 }
 
 #[repr(C)]
@@ -757,7 +5268,11 @@ pub struct RawDependency {
     version: OwnedSlice<u8>,
     git: OwnedSlice<u8>,
     path: OwnedSlice<u8>,
-    target: OwnedSlice<u8>
+    target: OwnedSlice<u8>,
+    branch: OwnedSlice<u8>,
+    tag: OwnedSlice<u8>,
+    rev: OwnedSlice<u8>,
+    kind: INT32
 }
 
 impl RawDependency {
@@ -767,7 +5282,1719 @@ impl RawDependency {
             version: OwnedSlice::from_str_opt(d.version),
             git: OwnedSlice::from_str_opt(d.git),
             path: OwnedSlice::from_str_opt(d.path),
-            target: OwnedSlice::from_str_opt(d.target)
+            target: OwnedSlice::from_str_opt(d.target),
+            branch: OwnedSlice::from_str_opt(d.branch),
+            tag: OwnedSlice::from_str_opt(d.tag),
+            rev: OwnedSlice::from_str_opt(d.rev),
+            kind: d.kind() as INT32
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn m(text: &str) -> Manifest {
+        Manifest::new(Document::parse(text).unwrap())
+    }
+
+    #[test]
+    fn rename() {
+        let text = "[package]\nname = \"foo\" # c\n\n[lib]\nname = \"foo\"\n[[bin]]\n\
+            name = \"foo\"\n[[bin]]\nname = \"other\"\n";
+        let mut a = m(text);
+        assert!(a.rename_package("bar", false));
+        assert_eq!(a.to_string(), text.replacen("\"foo\" # c", "\"bar\" # c", 1));
+        let mut b = m(text);
+        assert!(b.rename_package("bar", true));
+        assert_eq!(b.to_string(), text.replace("\"foo\"", "\"bar\""));
+        let mut c = m("bin = [{ name = \"foo\" }]\n[package]\nname = \"foo\"\n");
+        assert!(c.rename_package("bar", true));
+        assert_eq!(c.to_string(), "bin = [{ name = \"bar\" }]\n[package]\nname = \"bar\"\n");
+        assert!(!m("[lib]\n").rename_package("x", true));
+    }
+
+    #[test]
+    fn metadata_tools() {
+        let a = m("[package]\nname = \"x\"\n[package.metadata]\nstray = 1\n\
+            docs = { all = true }\n[package.metadata.wix]\nupgrade = \"x\"\n\
+            [package.metadata.wix.more]\n");
+        assert_eq!(a.metadata_tools(), vec!["docs", "wix"]);
+        let b = m("[package]\nname = \"x\"\n[package.metadata.deb]\n[package.metadata.wix]\n");
+        assert_eq!(b.metadata_tools(), vec!["deb", "wix"]);
+        assert!(m("[package]\n").metadata_tools().is_empty());
+    }
+
+    #[test]
+    fn semantically_eq_ignores_feature_order() {
+        let a = m("[dependencies]\nfoo = { version = \"1.0\", features = [\"a\", \"b\"] }\n\
+            [target.x86.dependencies]\n\
+            foo = { version = \"1.0\", features = [\"b\", \"a\", \"a\"], optional = false }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        assert_eq!(deps.len(), 2);
+        assert!(deps[0].semantically_eq(&deps[1]));
+        assert!(deps[1].semantically_eq(&deps[0]));
+    }
+
+    #[test]
+    fn semantically_eq_compares_rev() {
+        let a = m("[dependencies]\nfoo = { git = \"a\", rev = \"1234\" }\n\
+            [target.x86.dependencies]\nfoo = { git = \"a\", rev = \"5678\" }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        assert_eq!(deps.len(), 2);
+        assert!(!deps[0].semantically_eq(&deps[1]));
+        assert!(deps[0].semantically_eq(&deps[0]));
+    }
+
+    #[test]
+    fn expand_members() {
+        let a = m("[workspace]\nmembers = [\"tool\", \"crates/*\", \"x*y/\"]\n\
+            exclude = [\"crates/old\"]\n");
+        let ws = a.get_workspace().ok().unwrap().unwrap();
+        let entries = ["tool", "crates/a", "crates/old", "crates/b/c", "other", "xaay", "xy",
+                       "crates\\d"];
+        let r = ws.expand_members(&entries);
+        assert_eq!(r, vec!["tool", "crates/a", "crates\\d", "xaay", "xy"]);
+        assert!(m("").get_workspace().ok().unwrap().is_none());
+        assert!(m("[workspace]\nmembers = 1\n").get_workspace().is_err());
+    }
+
+    #[test]
+    fn error_count() {
+        let a = m("[package]\nname = 1\nauthors = \"x\"\n[dependencies]\nfoo = 1\n[features]\n\
+            default = [\"a\"]\na = []\nb = 3\n[profile.dev]\n");
+        assert_eq!(a.error_count(Section::Package), 2);
+        assert_eq!(a.error_count(Section::Dependencies), 1);
+        assert_eq!(a.error_count(Section::Targets), 0);
+        assert_eq!(a.error_count(Section::Features), 1);
+        assert_eq!(a.error_count(Section::Profiles), 0);
+        let b = m("[features]\ndefault = [\"a\"]\na = []\n");
+        let f = b.get_features().ok().unwrap();
+        assert_eq!(f, vec![("default", vec!["a"]), ("a", vec![])]);
+    }
+
+    #[test]
+    fn floats() {
+        let a = m("a = 6.24e23\nb = -1_000.5\nc = 3\n");
+        assert_eq!(a.get_float_raw(&["a"]).ok(), Some("6.24e23"));
+        assert_eq!(a.get_float(&["a"]).ok(), Some(6.24e23));
+        assert_eq!(a.get_float_raw(&["b"]).ok(), Some("-1_000.5"));
+        assert_eq!(a.get_float(&["b"]).ok(), Some(-1000.5));
+        match a.get_float(&["c"]) {
+            Err(QueryError::Conflict { kind: "integer", .. }) => {}
+            _ => panic!()
+        }
+        // toml_document predates `inf` and `nan`, manifests using them don't parse
+        assert!(Manifest::parse("a = inf\n").is_err());
+        assert!(Manifest::parse("a = nan\n").is_err());
+    }
+
+    #[test]
+    fn workspace_roles() {
+        assert_eq!(m("[workspace]\nmembers = []\n").workspace_role(), WorkspaceRole::Root);
+        assert_eq!(m("[package]\nname = \"a\"\n[workspace]\n").workspace_role(),
+                   WorkspaceRole::RootAndMember);
+        assert_eq!(m("[package]\nname = \"a\"\nworkspace = \"..\"\n").workspace_role(),
+                   WorkspaceRole::Member);
+        assert_eq!(m("[package]\nname = \"a\"\n").workspace_role(), WorkspaceRole::Standalone);
+        assert_eq!(m("").workspace_role(), WorkspaceRole::Standalone);
+    }
+
+    #[test]
+    fn workspace_paths() {
+        assert_eq!(m("[package]\nworkspace = \"../..\"\n").workspace_path().ok(),
+                   Some(Some("../..")));
+        assert_eq!(m("[package]\nname = \"a\"\n").workspace_path().ok(), Some(None));
+        assert_eq!(m("").workspace_path().ok(), Some(None));
+        match m("[package]\nworkspace = 1\n").workspace_path() {
+            Err(QueryError::Conflict { depth: 2, kind: "integer", .. }) => {}
+            _ => panic!()
+        }
+        match m("package = 1\n").workspace_path() {
+            Err(QueryError::Conflict { depth: 0, .. }) => {}
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn dup_dep_features() {
+        let a = m("[dependencies]\n\
+            foo = { version = \"1\", features = [\"a\", \"a\", \"b\", \"a\"] }\n\
+            bar = { version = \"1\", features = [\"x\", \"y\"] }\n\
+            [target.\"cfg(unix)\".dev-dependencies.baz]\nfeatures = [\"q\", \"q\"]\n");
+        let mut d = a.duplicate_dependency_features();
+        d.sort();
+        assert_eq!(d,
+                   vec![("baz".to_owned(), "q".to_owned()), ("foo".to_owned(), "a".to_owned())]);
+    }
+
+    #[test]
+    fn doc_url() {
+        let explicit = m("[package]\nname = \"a\"\ndocumentation = \"https://x\"\n");
+        assert_eq!(explicit.effective_documentation_url().ok(), Some("https://x".to_owned()));
+        assert_eq!(m("[package]\nname = \"foo\"\n").effective_documentation_url().ok(),
+                   Some("https://docs.rs/foo".to_owned()));
+        match m("[package]\nname = 1\n").effective_documentation_url() {
+            Err(QueryError::Conflict { kind: "integer", .. }) => {}
+            _ => panic!()
+        }
+        match m("[package]\nname = \"a\"\ndocumentation = true\n").effective_documentation_url() {
+            Err(QueryError::Conflict { kind: "boolean", .. }) => {}
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn optional_deps() {
+        let a = m("[dependencies]\nfoo = { version = \"1\", optional = true }\nbar = \"1\"\n\
+            qux = { version = \"1\", optional = false }\n[target.\"cfg(unix)\".dependencies]\n\
+            baz = { version = \"1\", optional = true }\n\
+            foo = { version = \"1\", optional = true }\n");
+        let mut d = a.optional_dependencies().ok().unwrap();
+        d.sort();
+        assert_eq!(d, vec!["baz", "foo"]);
+        let b = m("[dependencies]\nfoo = { version = \"1\", optional = \"yes\" }\n");
+        let e = b.optional_dependencies().err().unwrap();
+        assert_eq!(e.len(), 1);
+    }
+
+    #[test]
+    fn dep_syntax() {
+        let mut a = m("[dependencies]\nfoo = { version = \"1\", optional = true }\n\
+            bar = { version = \"1\", optional = true }\n\n[features]\ndefault = [\"dep:bar\"]\n");
+        assert_eq!(a.migrate_to_dep_syntax().ok(), Some(1));
+        assert_eq!(a.to_string(),
+                   "[dependencies]\nfoo = { version = \"1\", optional = true }\n\
+                       bar = { version = \"1\", optional = true }\n\n[features]\n\
+                       default = [\"dep:bar\"]\nfoo = [ \"dep:foo\" ]\n");
+        assert_eq!(a.migrate_to_dep_syntax().ok(), Some(0));
+        let mut b = m("[package]\nname = \"x\"\n\n[dependencies]\n\
+            foo = { version = \"1\", optional = true }\n");
+        assert_eq!(b.migrate_to_dep_syntax().ok(), Some(1));
+        assert_eq!(b.to_string(),
+                   "[package]\nname = \"x\"\n\n[dependencies]\n\
+                       foo = { version = \"1\", optional = true }\n\n[features]\n\
+                       foo = [ \"dep:foo\" ]\n");
+        let mut c = m("features = { a = [] }\n[dependencies]\n\
+            foo = { version = \"1\", optional = true }\n");
+        assert_eq!(c.migrate_to_dep_syntax().ok(), Some(1));
+        assert_eq!(c.get_features().ok().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn sem_hash() {
+        let a = m("[package]\nname = \"a\"\nversion = \"1.0\"\n[dependencies]\n\
+            foo = { version = \"1\", features = [\"x\"] }\n");
+        let b = m("# comment\n[package]\nversion   =   '1.0'   # trailing\n\nname = \"a\"\n\n\
+            [dependencies.foo]\nfeatures = [ \"x\" ]\nversion = \"1\"\n");
+        let c = m("[package]\nname = \"a\"\nversion = \"1.1\"\n[dependencies]\n\
+            foo = { version = \"1\", features = [\"x\"] }\n");
+        assert_eq!(a.semantic_hash(), b.semantic_hash());
+        assert!(a.semantic_hash() != c.semantic_hash());
+        assert!(m("a = 1").semantic_hash() != m("a = \"1\"").semantic_hash());
+        // Stored hashes have to keep matching
+        assert_eq!(m("").semantic_hash(), 0xa8c7f832281a39c5);
+        assert_eq!(m("a = 1").semantic_hash(), 0xebd1260ba3a02a8a);
+    }
+
+    #[test]
+    fn target_paths() {
+        let a = m("[package]\nname = \"pkg\"\n[lib]\nname = \"pkg\"\n[[bin]]\nname = \"pkg\"\n\
+            [[bin]]\nname = \"tool\"\n[[bin]]\nname = \"custom\"\npath = \"x/y.rs\"\n\
+            [[example]]\nname = \"ex\"\n");
+        let targets = a.get_output_targets().ok().unwrap();
+        let mut paths = targets.iter().map(|t| t.effective_path("pkg")).collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, vec!["examples/ex.rs", "src/bin/tool.rs", "src/lib.rs", "src/main.rs",
+                               "x/y.rs"]);
+    }
+
+    #[test]
+    fn sections() {
+        let text = "title = 1\n\n[package]\nname = \"a\"\n\n# deps\n[[bin]]\nname = \"b\"\n\
+            [target.\"cfg(unix)\".dependencies]\nfoo = \"1\"\n";
+        let r = m(text).section_ranges();
+        assert_eq!(r.len(), 3);
+        assert_eq!(r[0].0, "package");
+        assert_eq!(r[1].0, "bin");
+        assert_eq!(r[2].0, "target.\"cfg(unix)\".dependencies");
+        assert_eq!(&text[r[0].1..r[0].2], "[package]\nname = \"a\"\n\n# deps\n");
+        assert_eq!(r[0].2, r[1].1);
+        assert_eq!(&text[r[1].1..r[1].2], "[[bin]]\nname = \"b\"\n");
+        assert_eq!(r[1].2, r[2].1);
+        assert_eq!(&text[r[2].1..r[2].2], "[target.\"cfg(unix)\".dependencies]\nfoo = \"1\"\n");
+        assert_eq!(r[2].2, text.len());
+    }
+
+    #[test]
+    fn path_dep_version() {
+        fn suggest(text: &str) -> Option<Option<String>> {
+            m(text).suggest_path_dep_version().ok()
+        }
+        assert_eq!(suggest("[package]\nversion = \"1.2.3\"\n"), Some(Some("^1.2".to_owned())));
+        assert_eq!(suggest("[package]\nversion = \"1.2.3-beta.1+build\"\n"),
+                   Some(Some("^1.2.3-beta.1".to_owned())));
+        assert_eq!(suggest("[package]\nname = \"a\"\n"), Some(None));
+        assert!(m("[package]\nversion = 1\n").suggest_path_dep_version().is_err());
+    }
+
+    #[test]
+    fn move_targets() {
+        let mut a = m("[package]\nname = \"p\"\n\n# first\n[[bin]]\nname = \"a\"\n\
+            path = \"a.rs\"\n\n[[bin]]\nname = \"b\"\n\n[lib]\nname = \"l\"\n\n# third\n[[bin]]\n\
+            name = \"c\"");
+        assert!(a.move_target("bin", 0, 2));
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"p\"\n\n[[bin]]\nname = \"b\"\n\n[lib]\nname = \"l\"\n\n\
+                       # third\n[[bin]]\nname = \"c\"\n\n# first\n[[bin]]\nname = \"a\"\n\
+                       path = \"a.rs\"\n");
+        assert!(a.move_target("bin", 2, 0));
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"p\"\n\n# first\n[[bin]]\nname = \"a\"\npath = \"a.rs\"\n\
+                       \n[[bin]]\nname = \"b\"\n\n[lib]\nname = \"l\"\n\n# third\n[[bin]]\n\
+                       name = \"c\"\n");
+        assert!(!a.move_target("bin", 0, 3));
+        assert!(!a.move_target("test", 0, 0));
+    }
+
+    #[test]
+    fn feature_layouts() {
+        let a = m("[dependencies]\nfoo = { version = \"1\", features = [\"a\", \"b\"] }\n\
+            baz = \"1\"\n[dependencies.bar]\nfeatures = [\n    \"a\",\n    \"b\",\n]\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let mut l = deps.iter().map(|d| format!("{:?}", d.features_layout())).collect::<Vec<_>>();
+        l.sort();
+        assert_eq!(l, vec!["Absent", "Inline", "Multiline"]);
+    }
+
+    #[test]
+    fn redundant_targets() {
+        let a = m("[target.\"cfg(unix)\".dependencies]\na = \"1\"\n\
+            [target.\"cfg( all( unix ) )\".dependencies]\nb = \"1\"\n\
+            [target.\"cfg(any(unix, windows))\".dependencies]\nc = \"1\"\n\
+            [target.x86_64-pc-windows-msvc.dependencies]\nd = \"1\"\n\
+            [target.\"cfg(target_os = \\\"a b\\\")\".dependencies]\n\
+            [target.\"cfg(target_os = \\\"ab\\\")\".dependencies]\n");
+        assert_eq!(a.redundant_target_sections(),
+                   vec!["cfg( all( unix ) )".to_owned(), "x86_64-pc-windows-msvc".to_owned()]);
+        assert_eq!(m("[target.\"cfg(unix)\".dependencies]\n\
+            [target.\"cfg(windows)\".dependencies]\n").redundant_target_sections().len(), 0);
+        // A triple next to a cfg() that holds for it
+        let b = m("[target.x86_64-pc-windows-msvc.dependencies]\na = \"1\"\n\
+            [target.\"cfg(windows)\".dependencies]\nb = \"1\"\n\
+            [target.\"cfg(all(unix, target_pointer_width = \\\"64\\\"))\".dependencies]\nc = \"1\"\n\
+            [target.x86_64-unknown-linux-gnu.dependencies]\nd = \"1\"\n\
+            [target.i686-unknown-linux-gnu.dependencies]\ne = \"1\"\n\
+            [target.wasm32-unknown-unknown.dependencies]\nf = \"1\"\n");
+        assert_eq!(b.redundant_target_sections(),
+                   vec!["x86_64-pc-windows-msvc".to_owned(),
+                        "x86_64-unknown-linux-gnu".to_owned()]);
+        // cfg()s that don't hold for the triple, and a triple on its own
+        let c = m("[target.x86_64-pc-windows-msvc.dependencies]\na = \"1\"\n\
+            [target.\"cfg(target_os = \\\"linux\\\")\".dependencies]\nb = \"1\"\n\
+            [target.\"cfg(not(windows))\".dependencies]\nc = \"1\"\n");
+        assert_eq!(c.redundant_target_sections().len(), 0);
+        assert_eq!(m("[target.x86_64-pc-windows-msvc.dependencies]\na = \"1\"\n")
+                       .redundant_target_sections()
+                       .len(),
+                   0);
+    }
+
+    #[test]
+    fn profiles() {
+        let a = m("[profile.release]\nlto = true\n[profile.dev.package.foo]\nopt-level = 3\n\
+            [profile.my-custom]\ninherits = \"release\"\n[profile.dev]\ndebug = 1\n\
+            [profile.release.build-override]\nopt-level = 0\n");
+        assert_eq!(a.profile_names(), vec!["release", "dev", "my-custom"]);
+        assert_eq!(m("[package]\n").profile_names().len(), 0);
+        assert_eq!(m("profile = 1\n").profile_names().len(), 0);
+    }
+
+    #[test]
+    fn readme() {
+        assert_eq!(m("[package]\nreadme = \"R.md\"\n").get_readme().ok(),
+                   Some(Readme::Path("R.md")));
+        assert_eq!(m("[package]\nreadme = false\n").get_readme().ok(), Some(Readme::Disabled));
+        assert_eq!(m("[package]\nname = \"a\"\n").get_readme().ok(), Some(Readme::Default));
+        assert!(m("[package]\nreadme = 1\n").get_readme().is_err());
+        let mut a = m("[package]\nname = \"a\"\nreadme = false\nversion = \"1\"\n");
+        assert!(a.set_readme(Readme::Path("docs/README.md")));
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\nreadme = \"docs/README.md\"\nversion = \"1\"\n");
+        assert_eq!(a.get_readme().ok(), Some(Readme::Path("docs/README.md")));
+        assert!(a.set_readme(Readme::Disabled));
+        assert_eq!(a.get_readme().ok(), Some(Readme::Disabled));
+        assert!(a.set_readme(Readme::Default));
+        assert_eq!(a.to_string(), "[package]\nname = \"a\"\nversion = \"1\"\n");
+        let mut b = m("[dependencies]\nfoo = \"1\"\n");
+        assert!(b.set_readme(Readme::Path("R.md")));
+        assert_eq!(b.get_readme().ok(), Some(Readme::Path("R.md")));
+        assert!(!m("package = { name = \"a\" }\n").set_readme(Readme::Disabled));
+    }
+
+    #[test]
+    fn referenced_paths() {
+        let a = m("[package]\nname = \"x\"\nbuild = \"build.rs\"\nreadme = \"README.md\"\n\
+            license-file = \"LICENSE\"\ndescription = \"a/b.rs\"\ninclude = [\"src/**\"]\n\
+            exclude = [\"tmp\", \"old\"]\n\n[lib]\npath = \"src/l.rs\"\nname = \"n\"\n\n[[bin]]\n\
+            name = \"a\"\n\n[[bin]]\nname = \"b\"\npath = \"src/b.rs\"\n\n[dependencies]\n\
+            foo = { path = \"../foo\" }\nbar = \"1\"\n\n\
+            [target.\"cfg(target_env = \\\"x.y\\\")\".dev-dependencies]\n\
+            w = { path = \"../w\", version = \"0.1\" }\n");
+        let mut paths = a.referenced_paths()
+                         .into_iter()
+                         .map(|(p, v)| (p.join("|"), v))
+                         .collect::<Vec<_>>();
+        paths.sort();
+        let expected = vec![
+            ("bin|1|path", "src/b.rs"),
+            ("dependencies|foo|path", "../foo"),
+            ("lib|path", "src/l.rs"),
+            ("package|build", "build.rs"),
+            ("package|exclude|0", "tmp"),
+            ("package|exclude|1", "old"),
+            ("package|include|0", "src/**"),
+            ("package|license-file", "LICENSE"),
+            ("package|readme", "README.md"),
+            ("target|cfg(target_env = \"x.y\")|dev-dependencies|w|path", "../w"),
+        ];
+        let expected = expected.into_iter().map(|(p, v)| (p.to_owned(), v)).collect::<Vec<_>>();
+        assert_eq!(paths, expected);
+        assert!(m("[package]\nreadme = false\n").referenced_paths().is_empty());
+    }
+
+    #[test]
+    fn placeholder_version() {
+        let v = m("[package]\nname = \"a\"\nversion = \"0.0.0\"\n").validate();
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].severity(), Severity::Warning);
+        assert_eq!(m("[package]\nname = \"a\"\nversion = \"0.1.0\"\n").validate().len(), 0);
+        assert_eq!(m("[package]\nname = \"a\"\n").validate().len(), 0);
+    }
+
+    #[test]
+    fn default_features_spellings() {
+        let a = m("[dependencies]\na = { version = \"1\", default-features = false }\n\
+            b = { version = \"1\", default_features = false }\n\
+            c = { version = \"1\", default-features = false, default_features = true }\n\
+            d = { version = \"1\" }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let mut found = deps.iter().map(|d| (d.name, d.default_features)).collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(found, vec![("a", Some(false)), ("b", Some(false)), ("c", Some(false)),
+                               ("d", None)]);
+        let mut lints = a.validate()
+                         .into_iter()
+                         .filter(|e| e.severity == Severity::Info)
+                         .map(|e| e.path)
+                         .collect::<Vec<_>>();
+        lints.sort();
+        assert_eq!(lints, vec!["dependencies.b.default_features",
+                               "dependencies.c.default_features"]);
+    }
+
+    #[test]
+    fn dep_to_toml() {
+        fn first(manifest: &Manifest) -> String {
+            manifest.get_dependencies().ok().unwrap()[0].to_toml_string()
+        }
+        assert_eq!(first(&m("[dependencies]\nserde = \"1.0\"\n")), "serde = \"1.0\"");
+        let b = m("[dependencies.foo]\npath = \"../foo\"\nrev = \"abc\"\ngit = \"https://x/y\"\n\
+            version = \"0.1\"\nbranch = \"dev\"\ntag = \"v1\"\n");
+        assert_eq!(first(&b),
+                   "foo = { version = \"0.1\", git = \"https://x/y\", branch = \"dev\", \
+                    tag = \"v1\", rev = \"abc\", path = \"../foo\" }");
+        let c = m("[dependencies]\nbar = { default-features = false, optional = true, \
+            features = [\"a\", \"b\"], version = \"2\" }\n");
+        assert_eq!(first(&c),
+                   "bar = { version = \"2\", features = [\"a\", \"b\"], optional = true, \
+                    default-features = false }");
+        let d = m("[dependencies]\n\"we ird\" = { path = \"a\\\\b\" }\n");
+        assert_eq!(first(&d), "\"we ird\" = { path = \"a\\\\b\" }");
+    }
+
+    #[test]
+    fn kinds() {
+        let k = m("[package]\nname = \"a\"\nautobins = false\n[lib]\nname = \"a\"\n").crate_kinds();
+        assert!(k.has_lib() && !k.has_bins() && k.auto_lib() && !k.auto_bins());
+        let k = m("[package]\nname = \"a\"\n[[bin]]\nname = \"a\"\n").crate_kinds();
+        assert!(!k.has_lib() && k.has_bins() && k.auto_bins());
+        let k = m("[package]\nname = \"a\"\nbin = [{ name = \"x\" }]\n[lib]\n").crate_kinds();
+        assert!(!k.has_bins());
+        let k = m("[package]\nname = \"a\"\n[lib]\n[[bin]]\nname = \"b\"\n").crate_kinds();
+        assert!(k.has_lib() && k.has_bins());
+        let k = m("[workspace]\n").crate_kinds();
+        assert!(!k.auto_lib() && !k.auto_bins());
+    }
+
+    #[test]
+    fn meta_json() {
+        let a = m("[package]\nname = \"a\"\n[package.metadata.docs.rs]\nall-features = true\n\
+            targets = [\"x86_64\", \"i686\"]\n[package.metadata.deb]\n\
+            maintainer = \"Me \\\"me\\\"\"\ndepends = 1\nratio = 1.5\n");
+        let json = "{\"deb\":{\"depends\":1,\"maintainer\":\"Me \\\"me\\\"\",\"ratio\":1.5},\
+            \"docs\":{\"rs\":{\"all-features\":true,\"targets\":[\"x86_64\",\"i686\"]}}}";
+        assert_eq!(a.metadata_json().ok(), Some(Some(json.to_owned())));
+        assert_eq!(m("[package]\nname = \"a\"\n").metadata_json().ok(), Some(None));
+        assert!(m("[package]\nmetadata = 1\n").metadata_json().is_err());
+    }
+
+    #[test]
+    fn cas_version() {
+        let mut a = m("[dependencies]\nfoo = \"1.0\" # pinned\n\
+            bar = { version = \"0.3\", features = [] }\nbaz = { path = \"x\" }\n\
+            [target.\"cfg(unix)\".dependencies]\nfoo = \"2.0\"\n");
+        assert_eq!(a.compare_and_set_dependency_version("foo", None, "1.0", "1.1"),
+                   CasResult::Updated);
+        assert_eq!(a.compare_and_set_dependency_version("bar", None, "0.3", "0.4"),
+                   CasResult::Updated);
+        assert_eq!(a.compare_and_set_dependency_version("foo", Some("cfg(unix)"), "1.0", "3"),
+                   CasResult::Mismatch(Some("2.0".to_owned())));
+        assert_eq!(a.compare_and_set_dependency_version("baz", None, "1", "2"),
+                   CasResult::Mismatch(None));
+        assert_eq!(a.compare_and_set_dependency_version("qux", None, "1", "2"), CasResult::Absent);
+        assert_eq!(a.to_string(),
+                   "[dependencies]\nfoo = \"1.1\" # pinned\n\
+                       bar = { version = \"0.4\", features = [] }\nbaz = { path = \"x\" }\n\
+                       [target.\"cfg(unix)\".dependencies]\nfoo = \"2.0\"\n");
+    }
+
+    #[test]
+    fn editions() {
+        assert_eq!(m("[package]\nedition = \"2018\"\n[[bin]]\nname = \"a\"\n[lib]\n\
+            edition = \"2018\"\n").all_editions().ok(), Some(vec!["2018"]));
+        assert_eq!(m("[package]\nedition = \"2018\"\n[[bin]]\nname = \"a\"\nedition = \"2021\"\n\
+            [[bin]]\nname = \"b\"\n").all_editions().ok(), Some(vec!["2018", "2021"]));
+        assert!(m("[package]\nedition = 2018\n").all_editions().is_err());
+    }
+
+    #[test]
+    fn native_links() {
+        let a = m("[package]\nname = \"a\"\nlinks = \"z\"\nbuild = \"build/main.rs\"\n");
+        assert!(a.declares_native_link());
+        assert_eq!(a.get_build_script().ok(), Some(BuildScript::Path("build/main.rs")));
+        assert_eq!(a.validate().len(), 0);
+        assert_eq!(m("[package]\nname = \"a\"\nlinks = \"z\"\n").validate().len(), 0);
+        let b = m("[package]\nname = \"a\"\nlinks = \"z\"\nbuild = false\n");
+        let v = b.validate();
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].severity(), Severity::Warning);
+        assert!(!m("[package]\nname = \"a\"\nbuild = false\n").declares_native_link());
+    }
+
+    #[test]
+    fn dep_comments() {
+        let a = m("[dependencies]\nfoo = \"1.0\" # pinned for msrv\n# needed by\n#   the parser\n\
+            bar = { version = \"2\" }\nbaz = \"3\"\n\n# vendored\n[dependencies.qux]\n\
+            path = \"x\"\n[target.\"cfg(unix)\".build-dependencies]\ncc = \"1\"   #  build\n");
+        assert_eq!(a.dependency_comments(), vec![
+            ("foo".to_owned(), "pinned for msrv".to_owned()),
+            ("bar".to_owned(), "needed by\nthe parser".to_owned()),
+            ("qux".to_owned(), "vendored".to_owned()),
+            ("cc".to_owned(), "build".to_owned())]);
+    }
+
+    #[test]
+    fn feature_non_optional() {
+        let a = m("[dependencies]\nserde = \"1\"\nlog = { version = \"0.4\", optional = true }\n\
+            [features]\nbad = [\"serde\"]\n\
+            good = [\"log\", \"serde/derive\", \"dep:log\", \"other\"]\nother = []\n");
+        let v = a.validate();
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].path, "features.bad.serde");
+        assert_eq!(v[0].severity(), Severity::Error);
+        assert_eq!(m("[dependencies]\nlog = { version = \"0.4\", optional = true }\n[features]\n\
+            x = [\"log\"]\n").validate().len(), 0);
+        let b = m("[build-dependencies]\ncc = \"1\"\n[dev-dependencies]\nquickcheck = \"1\"\n\
+            [features]\nx = [\"cc\", \"quickcheck\"]\n");
+        let paths = b.validate().into_iter().map(|e| e.path).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["features.x.cc"]);
+    }
+
+    #[test]
+    fn target_build_and_dev_dependencies() {
+        let a = m("[target.\"cfg(windows)\".build-dependencies]\nembed = \"1\"\n\
+            [target.\"cfg(windows)\".dev-dependencies]\nwinapi = { version = \"0.2\" }\n\
+            [target.\"cfg(unix)\".dependencies]\nlibc = \"0.2\"\n");
+        let build = a.get_build_dependencies().ok().unwrap();
+        assert_eq!(build.iter().map(|d| (d.name(), d.target())).collect::<Vec<_>>(),
+                   vec![("embed", Some("cfg(windows)"))]);
+        let dev = a.get_dev_dependencies().ok().unwrap();
+        assert_eq!(dev.iter().map(|d| (d.name(), d.target())).collect::<Vec<_>>(),
+                   vec![("winapi", Some("cfg(windows)"))]);
+        let normal = a.get_dependencies().ok().unwrap();
+        assert_eq!(normal.iter().map(|d| d.name()).collect::<Vec<_>>(), vec!["libc"]);
+    }
+
+    #[test]
+    fn sorted_manifest() {
+        let a = m("[dependencies]\nzeta = \"1\"\n# alpha is important\n\
+            alpha = { version = \"1\", features = [\"b\", \"a\"], \
+            default-features = false } # pinned\n\n[features]\ndefault = []\n\n[package]\n\
+            version = \"0.1.0\"\nname = \"x\"\n\n[[bin]]\nname = \"b\"\npath = \"b.rs\"\n\n\
+            [[bin]]\npath = \"a.rs\"\nname = \"a\"\n");
+        let expected = "[package]\nname = \"x\"\nversion = \"0.1.0\"\n\n[[bin]]\nname = \"b\"\n\
+            path = \"b.rs\"\n\n[[bin]]\nname = \"a\"\npath = \"a.rs\"\n[dependencies]\n\
+            # alpha is important\nalpha = { default-features = false, features = [\"b\", \"a\"], \
+            version = \"1\" } # pinned\nzeta = \"1\"\n\n[features]\ndefault = []\n";
+        let got = a.sorted_string();
+        assert_eq!(got, expected);
+        assert_eq!(m(&got).semantic_hash(), a.semantic_hash());
+        let b = m("b = 1\na = { z = 1, y = { d = 2, c = 3 } }\n");
+        assert_eq!(b.sorted_string(), "a = { y = { c = 3, d = 2 }, z = 1 }\nb = 1\n");
+    }
+
+    #[test]
+    fn default_bin() {
+        assert_eq!(m("[package]\nname = \"app\"\n").default_bin_name().ok(), Some(Some("app")));
+        assert_eq!(m("[package]\nname = \"app\"\n[[bin]]\nname = \"x\"\n").default_bin_name().ok(),
+                   Some(None));
+        assert_eq!(m("[package]\nname = \"app\"\nautobins = false\n").default_bin_name().ok(),
+                   Some(None));
+        assert!(m("[package]\nname = 1\n").default_bin_name().is_err());
+    }
+
+    #[test]
+    fn edges() {
+        let a = m("[dependencies]\nserde = \"1\"\nfoo = { path = \"../foo\", \
+            version = \"0.1\" }\nbar = { git = \"https://x\", version = \"2\" }\n\
+            baz = { features = [] }\n[target.\"cfg(windows)\".dependencies]\nwinapi = \"0.2\"\n");
+        let edges = a.direct_edges().ok().unwrap();
+        let mut e = edges.iter()
+                         .map(|e| (e.to(), e.requirement(), e.kind(), e.target()))
+                         .collect::<Vec<_>>();
+        e.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(e, vec![
+            ("bar", Some("2"), DependencyKind::Git, None),
+            ("baz", None, DependencyKind::Detached, None),
+            ("foo", Some("0.1"), DependencyKind::Path, None),
+            ("serde", Some("1"), DependencyKind::Registry, None),
+            ("winapi", Some("0.2"), DependencyKind::Registry, Some("cfg(windows)")),
+        ]);
+        assert!(edges.iter().all(|e| e.section() == DependencySection::Normal));
+        let b = m("[dependencies]\nserde = \"1\"\n[dev-dependencies]\n\
+            quickcheck = { git = \"https://q\" }\n[build-dependencies]\ncc = \"1.0\"\n\
+            [target.x86_64-pc-windows-msvc.dev-dependencies]\nwinapi = { path = \"w\" }\n\
+            [target.\"cfg(unix)\".build-dependencies]\npkg-config = \"0.3\"\n\
+            [target.\"cfg(unix)\".dependencies]\nlibc = \"0.2\"\n");
+        let edges = b.direct_edges().ok().unwrap();
+        let mut e = edges.iter()
+                         .map(|e| (e.to(), e.requirement(), e.kind(), e.section(), e.target()))
+                         .collect::<Vec<_>>();
+        e.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(e, vec![
+            ("cc", Some("1.0"), DependencyKind::Registry, DependencySection::Build, None),
+            ("libc", Some("0.2"), DependencyKind::Registry, DependencySection::Normal,
+             Some("cfg(unix)")),
+            ("pkg-config", Some("0.3"), DependencyKind::Registry, DependencySection::Build,
+             Some("cfg(unix)")),
+            ("quickcheck", None, DependencyKind::Git, DependencySection::Dev, None),
+            ("serde", Some("1"), DependencyKind::Registry, DependencySection::Normal, None),
+            ("winapi", None, DependencyKind::Path, DependencySection::Dev,
+             Some("x86_64-pc-windows-msvc")),
+        ]);
+        // Errors of every section are reported together
+        let c = m("[dev-dependencies]\nfoo = 1\n[build-dependencies]\nbar = []\n");
+        assert_eq!(c.direct_edges().err().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn metadata_scalar() {
+        let mut a = m("[package]\nname = \"a\"\n");
+        assert!(a.set_metadata_scalar(&["docs", "rs", "all-features"], LeafValue::Boolean(true)));
+        let target = LeafValue::String("x86_64".to_owned());
+        assert!(a.set_metadata_scalar(&["docs", "rs", "default-target"], target));
+        let text = a.to_string();
+        let b = m(&text);
+        assert_eq!(b.get_metadata_scalar(&["docs", "rs", "all-features"]).ok().unwrap(),
+                   Some(LeafValue::Boolean(true)));
+        assert_eq!(b.get_metadata_scalar(&["docs", "rs", "default-target"]).ok().unwrap(),
+                   Some(LeafValue::String("x86_64".to_owned())));
+        assert_eq!(b.get_metadata_scalar(&["docs", "nope"]).ok().unwrap(), None);
+        assert!(b.get_metadata_scalar(&["docs", "rs"]).is_err());
+        let mut c = m("[package]\nname = \"a\"\n[package.metadata.docs.rs]\n\
+            all-features = false\nx = 1\n");
+        assert!(c.set_metadata_scalar(&["docs", "rs", "all-features"], LeafValue::Boolean(true)));
+        assert_eq!(c.to_string(),
+                   "[package]\nname = \"a\"\n[package.metadata.docs.rs]\nall-features = true\n\
+                       x = 1\n");
+        let mut d = m("[package]\nmetadata = { foo = 1 }\n");
+        assert!(!d.set_metadata_scalar(&["foo", "bar"], LeafValue::Integer(2)));
+        assert!(!d.set_metadata_scalar(&["bar", "baz"], LeafValue::Integer(2)));
+        let e = m("[package.metadata.tool]
+released = 1979-05-27T07:32:00Z
+");
+        match e.get_metadata_scalar(&["tool", "released"]) {
+            Err(QueryError::Conflict { kind: "datetime", .. }) => {}
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn ws_lints() {
+        let a = m("[package]  \nname = \"a\"\nversion =\t\"0.1.0\" \n\
+            description = \"ab\" # c\td\nauthors = [\"a\",\t\"b\"]\n");
+        assert_eq!(a.whitespace_lints(), vec![(1, "trailing whitespace"),
+                                              (3, "trailing whitespace"),
+                                              (5, "tab in value")]);
+        let b = m("[package]\nname = \"a\" # x\n\n[dependencies]\nfoo = \"1\"\n");
+        assert!(b.whitespace_lints().is_empty());
+    }
+
+    #[test]
+    fn resolve_dep_version() {
+        let root = m("[workspace]\nmembers = [\"a\"]\n[workspace.dependencies]\n\
+            serde = \"1.0.100\"\nrand = { version = \"0.8\", features = [] }\n");
+        let a = m("[dependencies]\nserde = { workspace = true }\nrand = { workspace = true, \
+            features = [\"x\"] }\nlog = \"0.4\"\nnope = { workspace = true }\n");
+        assert_eq!(a.resolve_dependency_version("serde", &root).ok().unwrap(),
+                   Some("1.0.100".to_owned()));
+        assert_eq!(a.resolve_dependency_version("rand", &root).ok().unwrap(),
+                   Some("0.8".to_owned()));
+        assert_eq!(a.resolve_dependency_version("log", &root).ok().unwrap(),
+                   Some("0.4".to_owned()));
+        assert_eq!(a.resolve_dependency_version("nope", &root).ok().unwrap(), None);
+        assert_eq!(a.resolve_dependency_version("missing", &root).ok().unwrap(), None);
+        let b = m("[dependencies]\nserde = { workspace = \"yes\" }\n");
+        assert!(b.resolve_dependency_version("serde", &root).is_err());
+    }
+
+    #[test]
+    fn dep_insertion_point() {
+        let text = "[package]\nname = \"a\"\n\n[dependencies]\nfoo = \"1\"\n\n# build\n\
+            [build-dependencies]\ncc = \"1\"\n";
+        let a = m(text);
+        assert_eq!(a.dependency_insertion_point(None),
+                   Some(text.find("\n\n# build").unwrap() + 1));
+        assert_eq!(a.dependency_insertion_point(Some("cfg(windows)")), Some(text.len()));
+        let b = m("[package]\nname = \"a\"\n");
+        assert_eq!(b.dependency_insertion_point(None), Some(21));
+        let c = m("dependencies = { foo = \"1\" }\n");
+        assert_eq!(c.dependency_insertion_point(None), None);
+        let implicit = "[package]\nname = \"a\"\n[dependencies.foo]\nversion = \"1\"\n";
+        let mut d = m(implicit);
+        assert_eq!(d.dependency_insertion_point(None), Some(implicit.len()));
+        assert!(d.add_dependency("bar", "2"));
+        assert!(d.to_string().starts_with(implicit));
+        assert!(d.to_string()[implicit.len()..].contains("bar = \"2\""));
+    }
+
+    #[test]
+    fn license_spdx() {
+        let lic = |l: &str| m(&format!("[package]\nlicense = \"{}\"\n", l)).validate_license_spdx();
+        assert!(lic("MIT OR Apache-2.0").is_none());
+        assert!(lic("MIT").is_none());
+        assert!(lic("(MIT OR Apache-2.0) AND GPL-2.0+ WITH Classpath-exception-2.0").is_none());
+        let e = lic("MIT/Apache-2.0").unwrap();
+        assert_eq!(e.severity(), Severity::Warning);
+        assert!(lic("MIT OR").is_some());
+        assert!(lic("(MIT").is_some());
+        assert!(lic("MIT Apache-2.0").is_some());
+        assert!(lic("").is_some());
+        assert_eq!(m("[package]\nlicense = \"MIT/Apache-2.0\"\n").validate().len(), 1);
+    }
+
+    #[test]
+    fn default_closure() {
+        let a = m("[features]\ndefault = [\"a\", \"b\"]\na = [\"c\"]\nb = [\"c\", \"serde/std\", \
+            \"dep:log\"]\nc = []\nd = []\n");
+        assert_eq!(a.default_feature_closure().ok().unwrap(), vec!["a", "b", "c"]);
+        assert!(m("[features]\na = [\"b\"]\n").default_feature_closure().ok().unwrap().is_empty());
+        assert!(m("[package]\n").default_feature_closure().ok().unwrap().is_empty());
+    }
+
+    #[test]
+    fn feature_active() {
+        let a = m("[features]\ndefault = [\"std\"]\nstd = [\"alloc\"]\nalloc = []\n\
+            full = [\"derive\"]\nderive = [\"proc\"]\nproc = []\n");
+        assert!(a.is_feature_active("alloc", &[], true).ok().unwrap());
+        assert!(!a.is_feature_active("alloc", &[], false).ok().unwrap());
+        assert!(a.is_feature_active("proc", &["full"], false).ok().unwrap());
+        assert!(a.is_feature_active("full", &["full"], false).ok().unwrap());
+        assert!(!a.is_feature_active("std", &["full"], false).ok().unwrap());
+        assert!(a.is_feature_active("default", &[], true).ok().unwrap());
+        let b = m("[features]
+std = []
+");
+        assert!(!b.is_feature_active("default", &[], true).ok().unwrap());
+        assert!(!m("").is_feature_active("default", &[], true).ok().unwrap());
+    }
+
+    #[test]
+    fn build_units() {
+        let a = m("[package]\nname = \"my-app\"\nedition = \"2018\"\n[lib]\n[[bin]]\n\
+            name = \"my-app\"\n[[bin]]\nname = \"tool\"\nrequired-features = [\"cli\"]\n\
+            edition = \"2021\"\n");
+        let u = a.build_units().ok().unwrap();
+        let u = u.iter()
+                 .map(|u| {
+                     (u.kind(), u.name(), u.effective_path(), u.required_features(), u.edition())
+                 })
+                 .collect::<Vec<_>>();
+        assert_eq!(u, vec![
+            ("lib", "my_app", "src/lib.rs", &[][..], "2018"),
+            ("bin", "my-app", "src/main.rs", &[][..], "2018"),
+            ("bin", "tool", "src/bin/tool.rs", &["cli".to_owned()][..], "2021"),
+        ]);
+        assert!(m("[[bin]]\nname = \"a\"\nrequired-features = \"x\"\n").build_units().is_err());
+    }
+
+    #[test]
+    fn lib_name_lint() {
+        fn lib(text: &str) -> usize {
+            m(text).validate().iter().filter(|e| e.severity() == Severity::Error).count()
         }
+        assert_eq!(lib("[package]\nname = \"my-crate\"\n[lib]\nname = \"my-crate\"\n"), 1);
+        assert_eq!(lib("[package]\nname = \"my-crate\"\n[lib]\nname = \"my_crate\"\n"), 0);
+        assert_eq!(lib("[package]\nname = \"my-crate\"\n"), 0);
+        assert_eq!(lib("[package]\nname = \"my.crate\"\n"), 1);
+        assert_eq!(lib("[package]\nname = \"caf\u{e9}\"\n"), 1);
+        assert_eq!(lib("[package]\nname = \"a\"\n[lib]\nname = \"2d\"\n"), 1);
+        assert_eq!(lib("[package]\nname = \"a\"\n[lib]\nname = \"\"\n"), 1);
+        assert_eq!(lib("[package]\nname = \"a\"\n[lib]\nname = \"d2\"\n"), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn owned_model() {
+        let model = {
+            let a = m("[package]\nname = \"a\"\nversion = \"0.1.0\"\nauthors = [\"x <x@y>\"]\n\
+                [dependencies]\nserde = { version = \"1\", features = [\"derive\"], \
+                optional = true }\n[[bin]]\nname = \"tool\"\ntest = false\n[features]\n\
+                default = [\"serde\"]\n");
+            a.to_owned_model()
+        };
+        let package = model.package().unwrap();
+        assert_eq!(package.name(), Some("a"));
+        assert_eq!(package.version(), Some("0.1.0"));
+        assert_eq!(package.authors(), &["x <x@y>".to_owned()]);
+        assert_eq!(package.edition(), None);
+        let dep = &model.dependencies()[0];
+        assert_eq!((dep.name(), dep.version(), dep.optional()), ("serde", Some("1"), Some(true)));
+        assert_eq!(dep.features(), &["derive".to_owned()]);
+        let bin = &model.output_targets()[0];
+        assert_eq!((bin.kind(), bin.name(), bin.test()), ("bin", Some("tool"), Some(false)));
+        assert_eq!(model.features(), &[("default".to_owned(), vec!["serde".to_owned()])]);
+    }
+
+    #[test]
+    fn rename_feature() {
+        let mut a = m("[package]\nname = \"a\"\n\n[features]\n# all of it\ndefault = [\"std\", \
+            \"serde/std\"]\n\nstd = []  # the std\nfull = [ \"std\", \"dep:std\" ]\n\
+            \"x\" = [\"std\"]\n");
+        assert_eq!(a.rename_feature("std", "use-std").ok().unwrap(), 3);
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\n\n[features]\n# all of it\ndefault = [\"use-std\", \
+                       \"serde/std\"]\n\nuse-std = []  # the std\nfull = [ \"use-std\", \
+                       \"dep:std\" ]\n\"x\" = [\"use-std\"]\n");
+        assert!(a.rename_feature("full", "default").is_err());
+        assert_eq!(a.rename_feature("nope", "other").ok().unwrap(), 0);
+        let mut b = m("[features]\n\"a b\" = []\nc = [\"a b\"]\n");
+        assert_eq!(b.rename_feature("a b", "ab").ok().unwrap(), 1);
+        assert_eq!(b.to_string(), "[features]\nab = []\nc = [\"ab\"]\n");
+        let mut c = m("features = { a = [] }\n");
+        assert!(c.rename_feature("a", "b").is_err());
+    }
+
+    #[test]
+    fn release_metadata() {
+        let a = m("[package]\nname = \"a\"\n\n[package.metadata.release]\nsign-commit = true\n\
+            sign-tag = true\npush = false\ntag-name = \"v{{version}}\"\n\
+            pre-release-commit-message = \"Release {{version}}\"\n\
+            pre-release-hook = [\"./scripts/changelog.sh\", \"{{version}}\"]\n\
+            allow-branch = [\"main\"]\ndependent-version = \"upgrade\"\n");
+        let c = a.get_release_metadata().ok().unwrap();
+        assert_eq!(c.sign_commit(), Some(true));
+        assert_eq!(c.push(), Some(false));
+        assert_eq!(c.publish(), None);
+        assert_eq!(c.tag_name(), Some("v{{version}}"));
+        assert_eq!(c.pre_release_hook().unwrap(),
+                   &["./scripts/changelog.sh".to_owned(), "{{version}}".to_owned()]);
+        assert_eq!(c.allow_branch().unwrap(), &["main".to_owned()]);
+        let b = m("[package.metadata.release]\npre-release-hook = \"make\"\nsign-tag = \"yes\"\n\
+            push = 1\n");
+        assert_eq!(b.get_release_metadata().err().unwrap().len(), 2);
+        assert!(m("[package]\n").get_release_metadata().ok().unwrap().sign_commit().is_none());
+    }
+
+    #[test]
+    fn set_string_impl() {
+        let mut a = m("# top\n[package]\nname = \"a\" # the name\nversion = 1\n\n[dependencies]\n\
+            foo = { version = \"1\", path = \"x\" }\n\n[[bin]]\nname = \"b\"\n");
+        assert!(a.set_string(&["package", "name"], "b"));
+        assert!(a.set_string(&["package", "version"], "0.1.0"));
+        assert!(a.set_string(&["package", "edition"], "2018"));
+        assert!(a.set_string(&["dependencies", "foo", "version"], "2"));
+        assert!(a.set_string(&["badges", "maintenance", "status"], "passively-maintained"));
+        assert!(a.set_string(&["cargo-features"], "x"));
+        assert!(!a.set_string(&["bin", "name"], "c"));
+        assert!(!a.set_string(&["package", "name", "x"], "c"));
+        assert!(!a.set_string(&["dependencies"], "c"));
+        assert_eq!(a.get_string(&["package", "name"]).ok(), Some("b"));
+        assert_eq!(a.get_string(&["badges", "maintenance", "status"]).ok(),
+                   Some("passively-maintained"));
+        assert_eq!(a.to_string(),
+                   "cargo-features = \"x\"\n# top\n[package]\nname = \"b\" # the name\n\
+                       version = \"0.1.0\"\nedition = \"2018\"\n\n[dependencies]\n\
+                       foo = { version = \"2\", path = \"x\" }\n\n[[bin]]\nname = \"b\"\n\n\
+                       [badges.maintenance]\nstatus = \"passively-maintained\"\n");
+    }
+
+    #[test]
+    fn set_string_array_impl() {
+        let mut a = m("[package]\nname = \"a\"\nauthors = [\"x\"] # who\nkeywords = \"oops\"\n");
+        assert!(a.set_string_array(&["package", "authors"], &["a <a@b>", "b"]));
+        assert!(a.set_string_array(&["package", "keywords"], &["gui"]));
+        assert!(a.set_string_array(&["package", "categories"], &[]));
+        assert!(a.set_string_array(&["workspace", "members"], &["x", "y"]));
+        assert!(!a.set_string_array(&["package", "name", "x"], &["x"]));
+        assert_eq!(a.get_string_array(&["package", "authors"]).ok().unwrap(),
+                   vec!["a <a@b>", "b"]);
+        assert_eq!(a.get_string_array(&["package", "keywords"]).ok().unwrap(), vec!["gui"]);
+        assert!(a.get_string_array(&["package", "categories"]).ok().unwrap().is_empty());
+        assert_eq!(a.get_string_array(&["workspace", "members"]).ok().unwrap(), vec!["x", "y"]);
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\nauthors = [ \"a <a@b>\" , \"b\" ] # who\n\
+                       keywords = [ \"gui\" ]\ncategories = []\n\n[workspace]\n\
+                       members = [ \"x\" , \"y\" ]\n");
+    }
+
+    #[test]
+    fn normalized_target_keys() {
+        let a = m("[target.\" x86_64-pc-windows-msvc \".dependencies]\nwinapi = \"0.3\"\n\
+            [target.\"cfg(all(unix, target_pointer_width = \\\"64\\\"))\".dependencies]\n\
+            libc = \"0.2\"\n[target.\" x86_64-pc-windows-msvc \".dev-dependencies]\nx = \"1\"\n\
+            [target]\nwasm32 = { dependencies = {} }\n");
+        assert_eq!(a.normalized_target_keys(), vec![
+            ("\" x86_64-pc-windows-msvc \"".to_owned(), "x86_64-pc-windows-msvc".to_owned()),
+            ("\"cfg(all(unix, target_pointer_width = \\\"64\\\"))\"".to_owned(), "cfg(all(unix, \
+                target_pointer_width = \"64\"))".to_owned()),
+            ("wasm32".to_owned(), "wasm32".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn deps_for_triple() {
+        let a = m("[dependencies]\nlog = \"0.4\"\n[target.\"cfg(unix)\".dependencies]\n\
+            libc = \"0.2\"\n[target.\"cfg(windows)\".dependencies]\nwinapi = \"0.3\"\n\
+            [target.\"x86_64-unknown-linux-gnu\".dependencies]\nexact = \"1\"\n\
+            [target.\"cfg(all(target_os = \\\"linux\\\", \
+            not(target_env = \\\"musl\\\")))\".dependencies]\nglibc = \"1\"\n\
+            [target.\"cfg(any(target_arch = \\\"wasm32\\\", \
+            target_pointer_width = \\\"32\\\"))\".dependencies]\nsmall = \"1\"\n");
+        let names = |triple: &str| {
+            let deps = a.dependencies_for_triple(triple).ok().unwrap();
+            let mut names = deps.iter().map(|d| d.name().to_owned()).collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(names("x86_64-unknown-linux-gnu"), vec!["exact", "glibc", "libc", "log"]);
+        assert_eq!(names("x86_64-unknown-linux-musl"), vec!["libc", "log"]);
+        assert_eq!(names("x86_64-pc-windows-msvc"), vec!["log", "winapi"]);
+        assert_eq!(names("wasm32-unknown-unknown"), vec!["log", "small"]);
+        assert_eq!(names("i686-pc-windows-gnu"), vec!["log", "small", "winapi"]);
+        assert_eq!(names("aarch64-apple-darwin"), vec!["libc", "log"]);
+    }
+
+    #[test]
+    fn get_set_bool() {
+        let mut a = m("[package]\nname = \"a\"\npublish = false # private\n[[bin]]\n\
+            name = \"b\"\n");
+        assert_eq!(a.get_bool(&["package", "publish"]).ok(), Some(false));
+        assert!(a.get_bool(&["package", "name"]).is_err());
+        assert!(a.set_bool(&["package", "publish"], true));
+        assert!(a.set_bool(&["package", "name"], true));
+        assert!(a.set_bool(&["profile", "release", "lto"], true));
+        assert!(!a.set_bool(&["bin", "test"], true));
+        assert_eq!(a.get_bool(&["profile", "release", "lto"]).ok(), Some(true));
+        assert_eq!(a.to_string(),
+                   "[package]\nname = true\npublish = true # private\n[[bin]]\nname = \"b\"\n\n\
+                       [profile.release]\nlto = true\n");
+    }
+
+    #[test]
+    fn header_newline_kept() {
+        let mut a = m("[package]\nreadme = \"R.md\"\nname = \"a\"\n[package.metadata.x]\ny = 1\n\
+            z = 2\n");
+        assert!(a.set_readme(Readme::Disabled));
+        assert!(a.set_metadata_scalar(&["x", "y"], LeafValue::String("s".to_owned())));
+        assert_eq!(a.to_string(),
+                   "[package]\nreadme = false\nname = \"a\"\n[package.metadata.x]\ny = \"s\"\n\
+                       z = 2\n");
+        assert!(a.set_readme(Readme::Default));
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\n[package.metadata.x]\ny = \"s\"\nz = 2\n");
+    }
+
+    #[test]
+    fn target_handles_survive_reparse() {
+        let mut a = m("[package]\nreadme = \"R.md\"\nname = \"a\"\n");
+        let handles = ["b", "c"].iter().map(|name| {
+            let mut bin = OutputTarget::bin(0);
+            bin.name = Some(name);
+            a.add_output_target(bin)
+        }).collect::<Vec<_>>();
+        // Replacing the first key under a header reparses to keep the line break
+        assert!(a.set_readme(Readme::Disabled));
+        let mut renamed = OutputTarget::bin(handles[1]);
+        renamed.name = Some("d");
+        assert!(a.set_output_target(renamed).ok().unwrap().is_none());
+        assert!(a.set_bool(&["package", "readme"], true));
+        assert!(a.set_string(&["package", "readme"], "R.md"));
+        assert!(a.remove_output_target(handles[0], "bin").is_ok());
+        assert_eq!(a.to_string(),
+                   "[package]\nreadme = \"R.md\"\nname = \"a\"\n\n[[bin]]\nname = \"d\"\n");
+        assert!(a.remove_output_target(handles[0], "bin").is_err());
+        let mut b = m("bin = [{ name = \"x\" }]\n[package]\nname = \"a\"\nversion = \"1\"\n");
+        let handle = b.get_output_targets().ok().unwrap()[0].handle;
+        assert!(b.remove(&["package", "name"]));
+        let mut renamed = OutputTarget::bin(handle);
+        renamed.name = Some("y");
+        assert!(b.set_output_target(renamed).is_ok());
+        assert_eq!(b.to_string(), "bin = [{ name = \"y\" }]\n[package]\nversion = \"1\"\n");
+        // An emptied table gets its line break back without a reparse
+        let mut c = m("[lib]\npath = \"l.rs\"\n[package]\nname = \"a\"\n");
+        let handle = c.get_output_targets().ok().unwrap()[0].handle;
+        assert!(c.remove(&["lib", "path"]));
+        assert_eq!(c.to_string(), "[lib]\n[package]\nname = \"a\"\n");
+        assert_eq!(c.get_output_targets().ok().unwrap()[0].handle, handle);
+    }
+
+
+    #[test]
+    fn has_package() {
+        let ws = m("[workspace]\nmembers = [\"a\", \"b\"]\n");
+        assert!(!ws.has_package());
+        assert_eq!(ws.error_count(Section::Package), 0);
+        let a = m("[package]\nname = \"a\"\nversion = \"0.1.0\"\n");
+        assert!(a.has_package());
+        assert_eq!(a.error_count(Section::Package), 0);
+        assert!(!m("package = \"x\"\n").has_package());
+    }
+
+    #[test]
+    fn get_set_integer() {
+        let mut a = m("[profile.release]\nopt-level = 2 # fast\ndebug = true\n");
+        assert_eq!(a.get_integer(&["profile", "release", "opt-level"]).ok(), Some(2));
+        assert!(a.set_integer(&["profile", "release", "opt-level"], 3));
+        assert!(a.set_integer(&["profile", "release", "codegen-units"], 1));
+        assert!(a.set_integer(&["profile", "dev", "opt-level"], 0));
+        assert!(!a.set_integer(&["profile", "release", "debug", "x"], 0));
+        assert_eq!(a.get_integer(&["profile", "release", "opt-level"]).ok(), Some(3));
+        assert_eq!(a.get_integer(&["profile", "release", "codegen-units"]).ok(), Some(1));
+        assert_eq!(a.get_integer(&["profile", "dev", "opt-level"]).ok(), Some(0));
+        match a.get_integer(&["profile", "release", "debug"]) {
+            Err(QueryError::Conflict { depth: 3, kind: "boolean", .. }) => {}
+            _ => panic!()
+        }
+        match a.get_integer(&["profile", "test", "debug"]) {
+            Err(QueryError::Vacant { .. }) => {}
+            _ => panic!()
+        }
+        assert_eq!(a.to_string(),
+                   "[profile.release]\nopt-level = 3 # fast\ndebug = true\ncodegen-units = 1\n\n\
+                       [profile.dev]\nopt-level = 0\n");
+    }
+
+    #[test]
+    fn integer_edition() {
+        let a = m("[package]\nname = \"a\"\nedition = 2021\n");
+        assert_eq!(a.validate().len(), 1);
+        assert_eq!(a.error_count(Section::Package), 1);
+        let b = m("[package]\nname = \"a\"\nedition = \"2021\"\n");
+        assert_eq!(b.validate().len(), 0);
+    }
+
+    #[test]
+    fn get_set_float() {
+        let mut a = m("[package.metadata.x]\nratio = 0.5\ncount = 3\n");
+        match a.get_float(&["package", "metadata", "x", "count"]) {
+            Err(QueryError::Conflict { depth: 4, kind: "integer", .. }) => {}
+            _ => panic!()
+        }
+        assert!(a.set_float(&["package", "metadata", "x", "ratio"], 0.75));
+        assert!(a.set_float(&["package", "metadata", "x", "scale"], 2.0));
+        assert!(!a.set_float(&["package", "metadata", "x", "nan"], f64::NAN));
+        let b = m(&a.to_string());
+        assert_eq!(b.get_float(&["package", "metadata", "x", "ratio"]).ok(), Some(0.75));
+        assert_eq!(b.get_float(&["package", "metadata", "x", "scale"]).ok(), Some(2.0));
+        assert!(a.set_float(&["package", "metadata", "x", "ratio"], 1.0));
+        assert_eq!(a.to_string(), "[package.metadata.x]\nratio = 1.0\ncount = 3\nscale = 2.0\n");
+        let mut c = m("[[bin]]\nname = \"b\"\n");
+        assert!(!c.set_float(&["bin", "x"], 2.0));
+        assert_eq!(c.to_string(), "[[bin]]\nname = \"b\"\n");
+        // Only the value's own text changes, handles stay valid
+        let mut d = m("ratio = { x = 1.5, y = 1 } # 1.5\n[[bin]]\nname = \"b\"\n");
+        let handle = d.get_output_targets().ok().unwrap()[0].handle;
+        assert!(d.set_float(&["ratio", "y"], 3.0));
+        let mut renamed = OutputTarget::bin(handle);
+        renamed.name = Some("c");
+        assert!(d.set_output_target(renamed).is_ok());
+        assert_eq!(d.to_string(),
+                   "ratio = { x = 1.5, y = 3.0 } # 1.5\n[[bin]]\nname = \"c\"\n");
+    }
+
+    #[test]
+    fn target_section_counts() {
+        let a = m("[lib]\n[[bin]]\nname = \"a\"\n[[example]]\nname = \"e\"\n[[bin]]\n\
+            name = \"b\"\n");
+        assert_eq!(a.target_section_counts(), vec![("bin", 2), ("example", 1)]);
+        assert!(m("[package]\n").target_section_counts().is_empty());
+    }
+
+    #[test]
+    fn get_datetime() {
+        let a = m("[package.metadata.x]\nreleased = 1979-05-27T07:32:00Z\nname = \"n\"\n");
+        assert_eq!(a.get_datetime(&["package", "metadata", "x", "released"]).ok(),
+                   Some("1979-05-27T07:32:00Z"));
+        match a.get_datetime(&["package", "metadata", "x", "name"]) {
+            Err(QueryError::Conflict { depth: 4, kind: "string", .. }) => {}
+            _ => panic!()
+        }
+        match a.get_datetime(&["package", "metadata", "y"]) {
+            Err(QueryError::Vacant { .. }) => {}
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn remove_path() {
+        let mut a = m("[package]\nname = \"a\"\nversion = \"0.1.0\"\n\n[dependencies]\n\
+            log = \"0.4\"\nfoo = { version = \"1\", optional = true }\n\n[dependencies.bar]\n\
+            version = \"2\"\n\n[dependencies.bar.baz]\nx = 1\n\n[[bin]]\nname = \"b\"\n");
+        assert!(a.remove(&["package", "name"]));
+        assert!(a.remove(&["dependencies", "foo", "optional"]));
+        assert!(a.remove(&["dependencies", "bar"]));
+        assert!(!a.remove(&["dependencies", "bar"]));
+        assert!(!a.remove(&["nope", "x"]));
+        assert!(a.remove(&["dependencies", "log"]));
+        assert!(a.remove(&["bin"]));
+        assert_eq!(a.to_string(),
+                   "[package]\nversion = \"0.1.0\"\n\n[dependencies]\nfoo = { version = \"1\"}\n");
+    }
+
+    #[test]
+    fn feature_note() {
+        let a = m("[dependencies]\nfoo = { version = \"1\", features = [\"x\", \"y\"] }\n\
+            bar = { version = \"1\", default-features = false, features = [\"x\"] }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let notes: Vec<_> = deps.iter().map(|d| d.effective_features_note()).collect();
+        let foo = notes.iter().find(|n| n.explicit().len() == 2).unwrap();
+        assert!(foo.default_features_enabled());
+        assert_eq!(foo.explicit(), &["x", "y"]);
+        let bar = notes.iter().find(|n| n.explicit().len() == 1).unwrap();
+        assert!(!bar.default_features_enabled());
+        assert_eq!(bar.explicit(), &["x"]);
+    }
+
+    #[test]
+    fn serialize_keeps_comments() {
+        let mut a = m("# top\n[package]\nname = \"a\" # the name\nversion = \"0.1.0\"\n");
+        assert!(a.set_string(&["package", "version"], "0.2.0"));
+        let text = a.serialize();
+        assert!(text.contains("# top\n"));
+        assert!(text.contains("# the name"));
+        assert!(text.contains("\"0.2.0\""));
+        assert_eq!(text, a.to_string());
+    }
+
+    #[test]
+    fn key_line() {
+        let a = m("# top\nedition = \"2018\"\n\n[package]\nname = \"a\"\n\n[dependencies]\n\
+            # serde\nserde = { version = \"1\" }\n[dependencies.foo]\nversion = \"1\"\n\
+            [target.x.dependencies]\nbar = \"1\"\n");
+        assert_eq!(a.key_line(&["edition"]), Some(2));
+        assert_eq!(a.key_line(&["package"]), Some(4));
+        assert_eq!(a.key_line(&["package", "name"]), Some(5));
+        assert_eq!(a.key_line(&["dependencies", "serde"]), Some(9));
+        assert_eq!(a.key_line(&["dependencies", "serde", "version"]), Some(9));
+        assert_eq!(a.key_line(&["dependencies", "foo"]), Some(10));
+        assert_eq!(a.key_line(&["dependencies", "foo", "version"]), Some(11));
+        assert_eq!(a.key_line(&["target"]), Some(12));
+        assert_eq!(a.key_line(&["target", "x", "dependencies", "bar"]), Some(13));
+        assert_eq!(a.key_line(&["dependencies", "nope"]), None);
+        assert_eq!(a.key_line(&[]), None);
+    }
+
+    #[test]
+    fn parse_manifest() {
+        let a = Manifest::parse("[package]\nname = \"a\"\n").ok().unwrap();
+        assert_eq!(a.get_string(&["package", "name"]).ok(), Some("a"));
+        let e = Manifest::parse("[package]\nname = \"a\"\nversion = \n").err().unwrap();
+        assert_eq!((e.line(), e.column()), (3, 11));
+        assert_eq!(e.message(), "expected a value");
+        assert_eq!(e.to_string(), "line 3, column 11: expected a value");
+        // ParseError::new digs the offset out of this, see there
+        let err = Document::parse("[package]\nname = \"a\"\nversion = \n").err().unwrap();
+        assert_eq!(format!("{:?}", err),
+                   "ParserError { lo: 31, hi: 32, desc: \"expected a value\" }");
+    }
+
+    #[test]
+    fn workspace_inheritance() {
+        let root = m("[workspace]\nmembers = [\"a\"]\n[workspace.package]\nversion = \"1.0.0\"\n\
+            [workspace.dependencies]\nserde = \"1\"\n");
+        let ok = m("[package]\nname = \"a\"\nversion = { workspace = true }\n[dependencies]\n\
+            serde = { workspace = true }\n[dev-dependencies.serde]\nworkspace = true\n");
+        assert_eq!(ok.validate_workspace_inheritance(&root).len(), 0);
+        let bad = m("[package]\nname = \"a\"\nedition = { workspace = true }\n[dependencies]\n\
+            serde = { workspace = true }\nrand = { workspace = true }\nlog = \"1\"\n");
+        let e = bad.validate_workspace_inheritance(&root);
+        assert_eq!(e.len(), 2);
+    }
+
+    #[test]
+    fn badges_display() {
+        let a = m("[badges]\nappveyor = { repository = \"a/b\", branch = \"master\", \
+            service = \"github\" }\nmaintenance = { status = \"actively-developed\" }\n");
+        let b = a.badges_display().ok().unwrap();
+        assert_eq!(b.len(), 2);
+        assert_eq!(b[0].provider(), "appveyor");
+        assert_eq!(b[0].fields(),
+                   &[("repository", "a/b"), ("branch", "master"), ("service", "github")]);
+        assert_eq!(b[1].provider(), "maintenance");
+        assert_eq!(b[1].fields(), &[("status", "actively-developed")]);
+        let cm = m("[badges.maintenance]\nstatus = \"none\"\n[badges.travis-ci]\n\
+            repository = \"x\"\n");
+        let c = cm.badges_display().ok().unwrap();
+        assert_eq!(c.iter().map(|b| b.provider()).collect::<Vec<_>>(),
+                   vec!["maintenance", "travis-ci"]);
+        let d = m("[badges]\nappveyor = { repository = 1 }\n");
+        assert_eq!(d.badges_display().err().unwrap().len(), 1);
+        assert_eq!(m("[package]\n").badges_display().ok().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn dependency_getters() {
+        let a = m("[dependencies]\nfoo = \"1.0\"\n[target.x86.dependencies]\n\
+            bar = { git = \"https://x\", path = \"../bar\" }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let foo = deps.iter().find(|d| d.name() == "foo").unwrap();
+        assert_eq!((foo.version(), foo.git(), foo.path(), foo.target()),
+                   (Some("1.0"), None, None, None));
+        let bar = deps.iter().find(|d| d.name() == "bar").unwrap();
+        assert_eq!((bar.version(), bar.git(), bar.path(), bar.target()),
+                   (None, Some("https://x"), Some("../bar"), Some("x86")));
+    }
+
+    #[test]
+    fn toggle_default_features() {
+        let src = "[package]\nname = \"a\"\n\n[dependencies]\nfoo = \"1.0\"\nbar = \"2\"\n";
+        let mut a = m(src);
+        assert!(a.set_dependency_default_features("foo", None, false));
+        assert_eq!(a.get_bool(&["dependencies", "foo", "default-features"]).ok(), Some(false));
+        assert_eq!(a.get_string(&["dependencies", "foo", "version"]).ok(), Some("1.0"));
+        assert!(a.set_dependency_default_features("foo", None, true));
+        assert_eq!(a.to_string(), src);
+        assert!(!a.set_dependency_default_features("nope", None, false));
+        let mut b = m("[dependencies]\nfoo = { version = \"1\", default_features = false, \
+            features = [\"x\"] }\n");
+        assert!(b.set_dependency_default_features("foo", None, true));
+        assert_eq!(b.get_bool(&["dependencies", "foo", "default_features"]).ok(), None);
+        assert_eq!(b.get_string(&["dependencies", "foo", "version"]).ok(), Some("1"));
+        let mut c = m("[dependencies.foo]\nversion = \"1\"\n[target.x.dependencies]\n\
+            bar = \"1\"\n");
+        assert!(c.set_dependency_default_features("foo", None, false));
+        assert!(c.set_dependency_default_features("bar", Some("x"), false));
+        assert!(c.set_dependency_default_features("foo", None, true));
+        assert!(c.set_dependency_default_features("bar", Some("x"), true));
+        assert_eq!(c.to_string(),
+                   "[dependencies.foo]\nversion = \"1\"\n[target.x.dependencies]\nbar = \"1\"\n");
+        // Nothing to remove, the inline table stays as written
+        let src = "[dependencies]\nfoo = { version = \"1\" }\n";
+        let mut d = m(src);
+        assert!(d.set_dependency_default_features("foo", None, true));
+        assert_eq!(d.to_string(), src);
+    }
+
+    #[test]
+    fn git_rev_round_trip() {
+        let a = m("[dependencies]\nfoo = { git = \"https://x/foo\", rev = \"abc123\" }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        assert_eq!((deps[0].git(), deps[0].rev(), deps[0].branch(), deps[0].tag()),
+                   (Some("https://x/foo"), Some("abc123"), None, None));
+        let line = deps[0].to_toml_string();
+        assert_eq!(line, "foo = { git = \"https://x/foo\", rev = \"abc123\" }");
+        let b = m(&format!("[dependencies]\n{}\n", line));
+        assert!(b.get_dependencies().ok().unwrap()[0].semantically_eq(&deps[0]));
+    }
+
+    #[test]
+    fn unknown_top_level() {
+        let a = m("cargo-features = []\n[package]\nname = \"a\"\n[depdendencies]\nfoo = \"1\"\n\
+            [depdendencies.bar]\nversion = \"1\"\n[[bin]]\nname = \"b\"\n[lints.rust]\n");
+        assert_eq!(a.unknown_top_level_keys(), vec!["cargo-features", "depdendencies"]);
+        let b = m("[package]\nname = \"a\"\n[lib]\n[dependencies]\n[dev-dependencies]\n\
+            [build-dependencies]\n[target.x.dependencies]\n[features]\n[[bench]]\n[[test]]\n\
+            [[example]]\n[profile.dev]\n[workspace]\n[patch.crates-io]\n[replace]\n[badges]\n");
+        assert_eq!(b.unknown_top_level_keys().len(), 0);
+    }
+
+    #[test]
+    fn optional_default_features_model() {
+        let a = m("[dependencies]\nfoo = { version = \"1\", optional = true, \
+            default-features = false }\nbar = \"1\"\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let foo = deps.iter().find(|d| d.name() == "foo").unwrap();
+        assert!(foo.optional() && !foo.default_features());
+        let bar = deps.iter().find(|d| d.name() == "bar").unwrap();
+        assert!(!bar.optional() && bar.default_features());
+        assert_eq!(m("[dependencies]\nfoo = { version = \"1\", optional = \"yes\", \
+            default-features = 0 }\n").get_dependencies().err().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn value_with_source() {
+        let a = m("[package]\nname = 'a'\n[package.metadata]\nsize = 1_000\nsigned = +5\n\
+            flag = true\nratio = 1e3\nwhen = 1979-05-27T07:32:00Z\nlist = []\n");
+        assert_eq!(a.value_with_source(&["package", "name"]),
+                   Some((LeafValue::String("a".to_owned()), "'a'")));
+        assert_eq!(a.value_with_source(&["package", "metadata", "size"]),
+                   Some((LeafValue::Integer(1000), "1_000")));
+        assert_eq!(a.value_with_source(&["package", "metadata", "signed"]),
+                   Some((LeafValue::Integer(5), "+5")));
+        assert_eq!(a.value_with_source(&["package", "metadata", "flag"]),
+                   Some((LeafValue::Boolean(true), "true")));
+        assert_eq!(a.value_with_source(&["package", "metadata", "ratio"]),
+                   Some((LeafValue::Float(1000.0), "1e3")));
+        assert_eq!(a.value_with_source(&["package", "metadata", "when"]), None);
+        assert_eq!(a.value_with_source(&["package", "metadata", "list"]), None);
+        assert_eq!(a.value_with_source(&["package", "metadata"]), None);
+        assert_eq!(a.value_with_source(&["package", "nope"]), None);
+    }
+
+    #[test]
+    fn dependency_features_errors() {
+        let a = m("[dependencies]\nfoo = { version = \"1\", features = [\"a\", \"b\"] }\n\
+            bar = { version = \"1\" }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let note = |name| deps.iter().find(|d| d.name() == name).unwrap().effective_features_note();
+        assert_eq!(note("foo").explicit(), &["a", "b"]);
+        assert_eq!(note("bar").explicit().len(), 0);
+        let errors = |text| m(text).get_dependencies().err().unwrap().len();
+        assert_eq!(errors("[dependencies]\nfoo = { version = \"1\", features = [1] }\n"), 1);
+        assert_eq!(errors("[dependencies]\nfoo = { version = \"1\", features = \"a\" }\n"), 1);
+    }
+
+    #[test]
+    fn dependency_kind() {
+        let a = m("[dependencies]\nreg = \"1\"\ngit = { git = \"https://x\" }\n\
+            both = { git = \"https://x\", version = \"1\" }\npath = { path = \"../p\", \
+            version = \"1\" }\nnone = { features = [\"a\"] }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let kind = |n: &str| deps.iter().find(|d| d.name() == n).unwrap().kind();
+        assert_eq!(kind("reg"), DependencyKind::Registry);
+        assert_eq!(kind("git"), DependencyKind::Git);
+        assert_eq!(kind("both"), DependencyKind::Git);
+        assert_eq!(kind("path"), DependencyKind::Path);
+        assert_eq!(kind("none"), DependencyKind::Detached);
+    }
+
+    #[test]
+    fn exclude_set() {
+        let mut a = m("[package]\nname = \"a\"\n");
+        assert!(a.add_exclude("*.tmp"));
+        assert!(a.add_exclude("target/**"));
+        assert!(!a.add_exclude("*.tmp"));
+        assert_eq!(a.get_string_array(&["package", "exclude"]).ok().unwrap(),
+                   vec!["*.tmp", "target/**"]);
+        assert!(a.remove_exclude("*.tmp"));
+        assert!(!a.remove_exclude("*.tmp"));
+        assert_eq!(a.get_string_array(&["package", "exclude"]).ok().unwrap(), vec!["target/**"]);
+        let mut b = m("[package]\nname = \"a\"\nexclude = [\"a\", \"b\"]\n");
+        assert!(b.remove_exclude("b"));
+        assert!(b.add_exclude("c"));
+        assert_eq!(b.to_string(), "[package]\nname = \"a\"\nexclude = [\"a\", \"c\" ]\n");
+    }
+
+    #[test]
+    fn effective_panic() {
+        let a = m("[profile.release]\npanic = \"abort\"\n[profile.dist]\ninherits = \"release\"\n\
+            [profile.ci]\ninherits = \"dist\"\n[profile.dev]\nopt-level = 1\n[profile.a]\n\
+            inherits = \"b\"\n[profile.b]\ninherits = \"a\"\n");
+        assert_eq!(a.effective_panic("release").ok().unwrap(), Some("abort"));
+        assert_eq!(a.effective_panic("dist").ok().unwrap(), Some("abort"));
+        assert_eq!(a.effective_panic("ci").ok().unwrap(), Some("abort"));
+        assert_eq!(a.effective_panic("bench").ok().unwrap(), Some("abort"));
+        assert_eq!(a.effective_panic("dev").ok().unwrap(), None);
+        assert_eq!(a.effective_panic("test").ok().unwrap(), None);
+        assert_eq!(a.effective_panic("nope").ok().unwrap(), None);
+        assert_eq!(a.effective_panic("a").err().unwrap().len(), 1);
+        assert_eq!(m("[package]\n").effective_panic("dev").ok().unwrap(), None);
+        assert_eq!(m("[profile.dev]\npanic = 1\n").effective_panic("test").err().unwrap().len(),
+                   1);
+    }
+
+    #[test]
+    fn dev_and_build_dependencies() {
+        let a = m("[dependencies]\nfoo = \"1\"\n[dev-dependencies]\nquickcheck = \"0.4\"\n\
+            [build-dependencies]\ncc = \"1\"\n[target.\"cfg(windows)\".dev-dependencies]\n\
+            winapi = { version = \"0.2\" }\n[target.\"cfg(windows)\".build-dependencies]\n\
+            embed = \"1\"\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        assert_eq!(deps.iter().map(|d| d.name()).collect::<Vec<_>>(), vec!["foo"]);
+        let dev = a.get_dev_dependencies().ok().unwrap();
+        let mut dev = dev.iter().map(|d| (d.name(), d.target())).collect::<Vec<_>>();
+        dev.sort();
+        assert_eq!(dev, vec![("quickcheck", None), ("winapi", Some("cfg(windows)"))]);
+        let build = a.get_build_dependencies().ok().unwrap();
+        let mut build = build.iter().map(|d| (d.name(), d.target())).collect::<Vec<_>>();
+        build.sort();
+        assert_eq!(build, vec![("cc", None), ("embed", Some("cfg(windows)"))]);
+        assert_eq!(m("[dev-dependencies]\nfoo = 1\n").get_dev_dependencies().err().unwrap().len(),
+                   1);
+    }
+
+    #[test]
+    fn add_dependency() {
+        let mut a = m("[package]\nname = \"a\"\n");
+        assert!(a.add_dependency("foo", "1.0"));
+        assert!(a.add_dependency("bar", "0.2"));
+        assert!(a.add_dependency("foo", "1.1"));
+        let deps = a.get_dependencies().ok().unwrap();
+        let mut deps = deps.iter().map(|d| (d.name(), d.version())).collect::<Vec<_>>();
+        deps.sort();
+        assert_eq!(deps, vec![("bar", Some("0.2")), ("foo", Some("1.1"))]);
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\n\n[dependencies]\nfoo = \"1.1\"\nbar = \"0.2\"\n");
+        let mut b = m("[dependencies]\nfoo = { version = \"1\", features = [\"x\"] }\n");
+        assert!(b.add_dependency("foo", "2"));
+        assert_eq!(b.to_string(),
+                   "[dependencies]\nfoo = { version = \"2\", features = [\"x\"] }\n");
+        assert!(!m("dependencies = 1\n").add_dependency("foo", "1"));
+        assert!(!m("[dependencies]\nfoo = 1\n").add_dependency("foo", "1"));
+    }
+
+    #[test]
+    fn requirement_kind() {
+        let a = m("[dependencies]\ncaret = \"^1.2\"\nbare = \"1.2.3\"\ntilde = \"~1.2\"\n\
+            exact = \"=1.2.3\"\nge = \">= 1, < 2\"\nlt = \"<2\"\nstar = \"*\"\nminor = \"1.*\"\n\
+            git = { git = \"https://x\" }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let kind = |n: &str| deps.iter().find(|d| d.name() == n).unwrap().requirement_kind();
+        assert_eq!(kind("caret"), Some(RequirementKind::Caret));
+        assert_eq!(kind("bare"), Some(RequirementKind::Caret));
+        assert_eq!(kind("tilde"), Some(RequirementKind::Tilde));
+        assert_eq!(kind("exact"), Some(RequirementKind::Exact));
+        assert_eq!(kind("ge"), Some(RequirementKind::Comparator));
+        assert_eq!(kind("lt"), Some(RequirementKind::Comparator));
+        assert_eq!(kind("star"), Some(RequirementKind::Wildcard));
+        assert_eq!(kind("minor"), Some(RequirementKind::Wildcard));
+        assert_eq!(kind("git"), None);
+    }
+
+    #[test]
+    fn remove_dependency() {
+        let mut a = m("[package]\nname = \"a\"\n\n[dependencies]\n# first\nfoo = \"1\"\n\
+            bar = { version = \"2\" } # keep\nbaz = \"3\"\n\n[target.x.dependencies]\n\
+            qux = \"1\"\n");
+        assert!(a.remove_dependency("bar", None));
+        assert!(!a.remove_dependency("bar", None));
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\n\n[dependencies]\n# first\nfoo = \"1\"\n\
+                       baz = \"3\"\n\n[target.x.dependencies]\nqux = \"1\"\n");
+        assert!(a.remove_dependency("foo", None));
+        assert!(a.remove_dependency("baz", None));
+        assert!(a.remove_dependency("qux", Some("x")));
+        assert!(!a.remove_dependency("qux", Some("y")));
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\n\n[dependencies]\n[target.x.dependencies]\n");
+        assert!(a.get_dependencies().ok().unwrap().is_empty());
+        let mut b = m("[dependencies.foo]\nversion = \"1\"\n[dependencies]\nbar = \"1\"\n");
+        assert!(b.remove_dependency("foo", None));
+        assert_eq!(b.to_string(), "[dependencies]\nbar = \"1\"\n");
+    }
+
+    #[test]
+    fn add_bin_full() {
+        let mut a = m("[package]\nname = \"a\"\nedition = \"2015\"\n\n[[bin]]\nname = \"old\"\n");
+        assert!(a.add_bin_full("tool", Some("src/tool.rs"), &["cli", "color"], Some("2018")));
+        assert!(a.add_bin_full("bare", None, &[], None));
+        assert!(!a.add_bin_full("old", None, &[], None));
+        let units = a.build_units().ok().unwrap();
+        assert_eq!(units.len(), 3);
+        assert_eq!((units[1].name(), units[1].effective_path(), units[1].edition()),
+                   ("tool", "src/tool.rs", "2018"));
+        assert_eq!(units[1].required_features(), &["cli".to_owned(), "color".to_owned()]);
+        assert_eq!((units[2].name(), units[2].effective_path(), units[2].edition()),
+                   ("bare", "src/bin/bare.rs", "2015"));
+        assert_eq!(units[2].required_features().len(), 0);
+        assert_eq!(a.get_output_targets().ok().unwrap().len(), 3);
+        assert!(!m("bin = [{ name = \"x\" }]\n").add_bin_full("y", None, &[], None));
+    }
+
+    #[test]
+    fn set_dependency_version() {
+        let src = "[dependencies]\nfoo = \"1.0\" # pinned\n\
+            bar = { version = \"0.1\",  features = [\"a\"] }\n[dependencies.baz]\n\
+            version = \"2\"\ndefault-features = false\n";
+        let mut a = m(src);
+        assert!(a.set_dependency_version("foo", "1.1"));
+        assert!(a.set_dependency_version("bar", "0.2"));
+        assert!(a.set_dependency_version("baz", "3"));
+        assert!(!a.set_dependency_version("nope", "1"));
+        assert_eq!(a.to_string(),
+                   src.replace("1.0", "1.1").replace("0.1", "0.2").replace("\"2\"", "\"3\""));
+    }
+
+    #[test]
+    fn empty_tables() {
+        let mut a = m("[package]\nname = \"a\"\n\n[dependencies]\n\n[target.x]\n\
+            [target.x.dependencies]\nfoo = \"1\"\n\n[workspace]\n\n[profile.dev]\n");
+        assert_eq!(a.empty_tables(),
+                   vec![vec!["dependencies".to_owned()],
+                        vec!["profile".to_owned(), "dev".to_owned()]]);
+        assert_eq!(a.prune_empty_tables(), 2);
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\n\n[target.x]\n[target.x.dependencies]\nfoo = \"1\"\n\
+                       \n[workspace]\n");
+        assert_eq!(a.empty_tables().len(), 0);
+        assert_eq!(a.prune_empty_tables(), 0);
+        let mut b = m("[package]\nname = \"a\"\n# none yet\n[dependencies]\n[lib]\npath = \"x\"\n");
+        assert_eq!(b.prune_empty_tables(), 1);
+        assert_eq!(b.to_string(), "[package]\nname = \"a\"\n# none yet\n[lib]\npath = \"x\"\n");
+        let mut c = m("[package]\nname = \"a\"\n\n[dependencies]\n");
+        let handle = c.add_output_target(OutputTarget::bin(0));
+        assert_eq!(c.prune_empty_tables(), 1);
+        assert!(c.remove_output_target(handle, "bin").is_err());
+    }
+
+    #[test]
+    fn next_prerelease() {
+        let mut a = m("[package]\nname = \"a\"\nversion = \"1.2.3\" # bumped by CI\n");
+        assert_eq!(a.next_prerelease("alpha").ok().unwrap(), "1.2.4-alpha.1");
+        assert_eq!(a.next_prerelease("alpha").ok().unwrap(), "1.2.4-alpha.2");
+        assert_eq!(a.to_string(),
+                   "[package]\nname = \"a\"\nversion = \"1.2.4-alpha.2\" # bumped by CI\n");
+        match a.next_prerelease("beta") {
+            Err(BumpError::OtherPrerelease) => {}
+            _ => panic!()
+        }
+        let mut b = m("[package]\nversion = \"0.9.0+build.5\"\n");
+        assert_eq!(b.next_prerelease("rc").ok().unwrap(), "0.9.1-rc.1+build.5");
+        match m("[package]\nversion = \"1.2\"\n").next_prerelease("rc") {
+            Err(BumpError::Malformed) => {}
+            _ => panic!()
+        }
+        let mut c = m("[package]\nname = \"a\"\n");
+        match c.next_prerelease("rc") {
+            Err(BumpError::Query(QueryError::Vacant { .. })) => {}
+            _ => panic!()
+        }
+        let mut d = m("[package]\nversion = 1\n");
+        match d.next_prerelease("rc") {
+            Err(BumpError::Query(QueryError::Conflict { .. })) => {}
+            _ => panic!()
+        }
+        assert_eq!(d.to_string(), "[package]\nversion = 1\n");
+    }
+
+    #[test]
+    fn dev_dependencies_tagged() {
+        let a = m("[dependencies]\nfoo = \"1\"\n[dev-dependencies]\nquickcheck = \"0.4\"\n\
+            [target.x86.dev-dependencies]\nwinapi = { version = \"0.2\" }\n");
+        let dev = a.get_dev_dependencies().ok().unwrap();
+        let mut dev = dev.iter().map(|d| (d.name(), d.target(), d.section())).collect::<Vec<_>>();
+        dev.sort_by_key(|d| d.0);
+        assert_eq!(dev,
+                   vec![("quickcheck", None, DependencySection::Dev),
+                        ("winapi", Some("x86"), DependencySection::Dev)]);
+        assert_eq!(a.get_dependencies().ok().unwrap()[0].section(), DependencySection::Normal);
+        let b = m("[build-dependencies]\ncc = \"1\"\n");
+        assert_eq!(b.get_build_dependencies().ok().unwrap()[0].section(), DependencySection::Build);
+    }
+
+    #[test]
+    fn workspace_members() {
+        let a = m("[workspace]\nmembers = [\"a\", \"crates/*\"]\nexclude = [\"old\"]\n");
+        assert_eq!(a.get_workspace_members().ok().unwrap(), vec!["a", "crates/*"]);
+        assert_eq!(a.get_workspace_exclude().ok().unwrap(), vec!["old"]);
+        assert!(m("[package]\nname = \"x\"\n").get_workspace_members().ok().unwrap().is_empty());
+        assert!(m("[workspace]\n").get_workspace_members().ok().unwrap().is_empty());
+        let errors = |text| m(text).get_workspace_members().err().unwrap().len();
+        assert_eq!(errors("[workspace]\nmembers = \"a\"\n"), 1);
+        assert_eq!(errors("workspace = 1\n"), 1);
+    }
+
+    #[test]
+    fn dependency_stable_key() {
+        let a = m("[dependencies]\nserde = \"1.0\"\n[dev-dependencies]\nserde = \"1.0\"\n\
+            [target.x86.dependencies]\nserde = \"1.0\"\n");
+        let b = m("[dependencies]\nserde = { version = \"1.2\", features = [\"derive\"] }\n");
+        let deps = a.get_dependencies().ok().unwrap();
+        let keys = deps.iter().map(|d| d.stable_key()).collect::<Vec<_>>();
+        assert!(keys.contains(&"normal::global::serde".to_owned()));
+        assert!(keys.contains(&"normal::x86::serde".to_owned()));
+        assert_eq!(b.get_dependencies().ok().unwrap()[0].stable_key(), "normal::global::serde");
+        assert_eq!(a.get_dev_dependencies().ok().unwrap()[0].stable_key(), "dev::global::serde");
+    }
+
+    #[test]
+    fn package_view() {
+        let a = m("[package]\nname = \"foo\"\nversion = \"0.1.0\"\nauthors = [\"A <a@x>\", \
+            \"B\"]\nedition = \"2018\"\nlicense = \"MIT\"\n");
+        let p = a.package().unwrap();
+        assert_eq!((p.name(), p.version(), p.edition(), p.license(), p.description()),
+                   (Some("foo"), Some("0.1.0"), Some("2018"), Some("MIT"), None));
+        assert_eq!(p.authors(), vec!["A <a@x>", "B"]);
+        assert!(m("[workspace]\nmembers = []\n").package().is_none());
+        assert_eq!(m("[package]\nedition = 2018\n").package().unwrap().edition(), None);
+    }
+
+    #[test]
+    fn dependencies_spans() {
+        let text = "[package]\nname = \"x\"\n\n[dependencies]\nserde = \"1.0\" # json\n\
+            rand = { version = \"0.3\" }\n\n[dependencies.log]\nversion = \"0.3\"\n\n# next\n\
+            [target.x86.dev-dependencies]\nwinapi = \"0.2\"\n";
+        let a = m(text);
+        let deps = a.dependencies_with_spans().ok().unwrap();
+        let find = |n: &str| deps.iter().find(|d| d.0.name() == n).unwrap().1;
+        let slice = |(a, b): (usize, usize)| &text[a..b];
+        assert_eq!(slice(find("serde")), "serde = \"1.0\"");
+        assert_eq!(slice(find("rand")), "rand = { version = \"0.3\" }");
+        assert_eq!(slice(find("log")), "[dependencies.log]\nversion = \"0.3\"");
+        assert_eq!(slice(find("winapi")), "winapi = \"0.2\"");
+        let (s1, s2) = (find("serde"), find("rand"));
+        assert!(s1.1 <= s2.0);
+        assert_eq!(a.key_line(&["dependencies", "rand"]), Some(6));
+        assert_eq!(a.key_line(&["dependencies", "log"]), Some(8));
+        let text = "dependencies = { a = \"1\", b = { version = \"2\" } }\n";
+        let b = m(text);
+        let deps = b.dependencies_with_spans().ok().unwrap();
+        let mut spans = deps.iter().map(|d| &text[(d.1).0..(d.1).1]).collect::<Vec<_>>();
+        spans.sort();
+        assert_eq!(spans, vec!["a = \"1\"", "b = { version = \"2\" }"]);
+        let (start, end) = b.entry_span(&["dependencies", "b", "version"]).unwrap();
+        assert_eq!(&text[start..end], "version = \"2\"");
+    }
+
+    #[test]
+    fn set_edition_validates() {
+        let mut a = m("[package]\nname = \"x\"\n");
+        assert_eq!(a.set_edition("2018"), Ok(()));
+        assert_eq!(a.get_string(&["package", "edition"]).ok(), Some("2018"));
+        assert_eq!(a.set_edition("2020"), Err(EditionError::Unknown { allowed: EDITIONS }));
+        assert_eq!(a.get_string(&["package", "edition"]).ok(), Some("2018"));
+        assert_eq!(m("package = 1\n").set_edition("2021"), Err(EditionError::Conflict));
+    }
+
+    #[test]
+    fn output_target_required_features() {
+        let a = m("[[bin]]\nname = \"cli\"\nrequired-features = [\"clap\", \"color\"]\n\n\
+            [[example]]\nname = \"e\"\n");
+        let targets = a.get_output_targets().ok().unwrap();
+        assert_eq!(targets[0].required_features(), &["clap", "color"]);
+        assert!(targets[1].required_features().is_empty());
+        assert_eq!(a.build_units().ok().unwrap()[0].required_features(),
+                   &["clap".to_owned(), "color".to_owned()]);
+        let b = m("[[bin]]\nname = \"cli\"\nrequired-features = \"clap\"\n");
+        assert_eq!(b.get_output_targets().err().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn lib_crate_type() {
+        let ct = |s: &str| {
+            let a = m(s);
+            let targets = a.get_output_targets().ok().unwrap();
+            targets[0].crate_type().iter().map(|c| c.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(ct("[lib]\ncrate-type = [\"cdylib\", \"rlib\"]\n"), vec!["cdylib", "rlib"]);
+        assert_eq!(ct("[lib]\ncrate_type = \"staticlib\"\n"), vec!["staticlib"]);
+        assert_eq!(ct("[lib]\ncrate_type = [\"dylib\"]\n"), vec!["dylib"]);
+        assert!(ct("[[bin]]\nname = \"x\"\ncrate-type = [\"cdylib\"]\n").is_empty());
+        assert!(m("[lib]\ncrate-type = \"cdylib\"\n").get_output_targets().is_err());
+        assert!(m("[lib]\ncrate-type = [1]\n").get_output_targets().is_err());
+    }
+
+    #[test]
+    fn lib_proc_macro() {
+        let proc_macro = |s: &str| m(s).get_output_targets().ok().unwrap()[0].proc_macro();
+        let a = m("[lib]\nproc-macro = true\n");
+        assert!(a.get_output_targets().ok().unwrap()[0].proc_macro());
+        assert!(a.to_owned_model().output_targets()[0].proc_macro());
+        assert!(!proc_macro("[lib]\npath = \"x.rs\"\n"));
+        assert!(!proc_macro("[[bin]]\nname = \"x\"\nproc-macro = true\n"));
+        assert_eq!(m("[lib]\nproc-macro = \"yes\"\n").get_output_targets().err().unwrap().len(),
+                   1);
+    }
+
+    #[test]
+    fn output_target_edition() {
+        let a = m("[package]\nname = \"p\"\nedition = \"2015\"\n\n[[bin]]\nname = \"new\"\n\
+            edition = \"2018\"\n\n[[bin]]\nname = \"old\"\n");
+        let targets = a.get_output_targets().ok().unwrap();
+        assert_eq!((targets[0].edition(), targets[1].edition()), (Some("2018"), None));
+        let units = a.build_units().ok().unwrap();
+        assert_eq!((units[0].edition(), units[1].edition()), ("2018", "2015"));
+        assert!(m("[[bin]]\nname = \"x\"\nedition = 2018\n").get_output_targets().is_err());
+    }
+
+    #[test]
+    fn query_error_display() {
+        let a = m("[package]\nname = \"x\"\n");
+        assert_eq!(a.get_string(&["package", "version"]).err().unwrap().to_string(),
+                   "no value found at `package.version`");
+        assert_eq!(a.get_string(&["package", "name", "x"]).err().unwrap().to_string(),
+                   "unexpected string at `package.name`");
+        fn boxed(a: &Manifest) -> Result<(), Box<dyn std::error::Error>> {
+            a.get_string(&["lib", "path"])?;
+            Ok(())
+        }
+        assert!(boxed(&a).is_err());
+    }
+
+    #[test]
+    fn query_error_path() {
+        let a = m("[package]\nname = \"x\"\n[package.metadata.foo]\nbar = 1\n");
+        let path = |p: &[&str]| a.get_string(p).err().unwrap().path().to_owned();
+        assert_eq!(path(&["package", "metadata", "foo", "baz"]), "package.metadata.foo.baz");
+        assert_eq!(path(&["package", "metadata", "qux", "baz"]), "package.metadata.qux");
+        assert_eq!(path(&["lib", "path"]), "lib");
+        assert_eq!(path(&["package", "name", "x"]), "package.name");
+        assert_eq!(path(&["package", "metadata", "foo", "bar"]), "package.metadata.foo.bar");
+        match a.get_string(&["package", "metadata", "foo", "baz"]) {
+            Err(QueryError::Vacant { depth: 2, .. }) => {}
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn path_error_display() {
+        let a = m("[dependencies.foo]\nversion = 1\n[dependencies]\nbar = 1\n");
+        let errors = a.get_dependencies().err().unwrap();
+        let mut messages = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(messages, vec!["dependencies.bar: expected string, got integer"]);
+        let error = m("workspace = 1\n").get_workspace_members().err().unwrap().remove(0);
+        let boxed: Box<dyn std::error::Error> = Box::new(error);
+        assert_eq!(boxed.to_string(), "workspace: expected table, got integer");
+    }
+
+    #[test]
+    fn owned_slice_drops_once() {
+        struct Counted(Rc<Cell<usize>>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+        let drops = Rc::new(Cell::new(0));
+        let slice = OwnedSlice::from_slice(&[1, 2, 3], |_| Counted(drops.clone()));
+        assert_eq!(drops.get(), 0);
+        drop(slice);
+        assert_eq!(drops.get(), 3);
+        // Drop nulls the slice, so dropping it again frees nothing twice
+        let mut slice = OwnedSlice::from_slice(&[1, 2], |_| Counted(drops.clone()));
+        unsafe { ptr::drop_in_place(&mut slice) };
+        assert_eq!(drops.get(), 5);
+        assert!(slice.data.arr.is_null());
+        assert_eq!(slice.data.len, 0);
+        drop(slice);
+        assert_eq!(drops.get(), 5);
+        drop(OwnedSlice::<Counted>::empty());
+        assert_eq!(drops.get(), 5);
+        assert_eq!(Rc::strong_count(&drops), 1);
+    }
+}